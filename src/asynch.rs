@@ -0,0 +1,377 @@
+//! Async flavour of the driver for executors such as Embassy
+//!
+//! [`AsyncSC16IS752`] covers bring-up and the FIFO data path. Where the blocking driver spins
+//! on LSR it yields to the executor between polls instead. The register encoding (divisor,
+//! line format, sub-address) is the one the blocking driver uses.
+//!
+//! [`AsyncTransport`] is local to this crate rather than built on `embedded-hal-async`: the
+//! `embedded-hal-async` releases that pair with embedded-hal 1.0.0-alpha.8 need a nightly
+//! compiler, and this crate builds on stable. Adapting an async bus takes a few lines, e.g.
+//! over an `embedded_hal_async::i2c::I2c` from a newer HAL:
+//!
+//! ```ignore
+//! struct AsyncI2c<I2C> {
+//!     address: u8,
+//!     i2c: I2C,
+//! }
+//!
+//! impl<I2C: embedded_hal_async::i2c::I2c> AsyncTransport for AsyncI2c<I2C> {
+//!     type Error = I2C::Error;
+//!
+//!     async fn read_register(&mut self, channel: Channel, reg: u8) -> Result<u8, I2C::Error> {
+//!         let mut value = [0];
+//!         let sub_address = reg << 3 | (channel as u8) << 1;
+//!         self.i2c.write_read(self.address, &[sub_address], &mut value).await?;
+//!         Ok(value[0])
+//!     }
+//!
+//!     async fn write_register(&mut self, channel: Channel, reg: u8, value: u8) -> Result<(), I2C::Error> {
+//!         let sub_address = reg << 3 | (channel as u8) << 1;
+//!         self.i2c.write(self.address, &[sub_address, value]).await
+//!     }
+//! }
+//! ```
+
+use crate::{
+    checked_divisor, fifo_enable_writes, line_format, prescaler, Channel, Error, UartConfig,
+    CRYSTAL_FREQ, FIFO_DEPTH, THR_EMPTY_POLLS,
+};
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+/// Async counterpart of [`crate::Transport`]
+///
+/// Implement this over an async I2C or SPI bus, e.g. `embedded_hal_async::i2c::I2c`, using
+/// the same sub-address byte as the blocking transports: register in bits 6:3 and channel in
+/// bits 2:1, with bit 7 set for SPI reads.
+#[allow(async_fn_in_trait)]
+pub trait AsyncTransport {
+    /// Error type of the underlying bus
+    type Error: core::fmt::Debug;
+
+    /// Reads a single register
+    async fn read_register(&mut self, channel: Channel, reg: u8) -> Result<u8, Self::Error>;
+
+    /// Writes a single register
+    async fn write_register(
+        &mut self,
+        channel: Channel,
+        reg: u8,
+        value: u8,
+    ) -> Result<(), Self::Error>;
+
+    /// Reads `buf.len()` bytes from the same register in one transaction, used to drain RHR
+    async fn read_registers(
+        &mut self,
+        channel: Channel,
+        reg: u8,
+        buf: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        for byte in buf.iter_mut() {
+            *byte = self.read_register(channel, reg).await?;
+        }
+        Ok(())
+    }
+
+    /// Writes `data` to the same register in one transaction, used to fill THR
+    async fn write_registers(
+        &mut self,
+        channel: Channel,
+        reg: u8,
+        data: &[u8],
+    ) -> Result<(), Self::Error> {
+        for byte in data {
+            self.write_register(channel, reg, *byte).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns `Pending` once so other tasks get to run between register polls
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// SC16IS752 driven over an [`AsyncTransport`]
+#[derive(Debug)]
+pub struct AsyncSC16IS752<T> {
+    transport: T,
+    fcr: [u8; 2],
+    crystal_hz: u32,
+}
+
+impl<T, E> AsyncSC16IS752<T>
+where
+    T: AsyncTransport<Error = E>,
+{
+    /// Driver for a chip with the usual 1.8432 MHz crystal
+    pub fn new(transport: T) -> Self {
+        Self::new_with_crystal(transport, CRYSTAL_FREQ)
+    }
+
+    /// Like `new` for boards fitted with a crystal other than 1.8432 MHz
+    pub fn new_with_crystal(transport: T, crystal_hz: u32) -> Self {
+        Self {
+            transport,
+            fcr: [0u8; 2],
+            crystal_hz,
+        }
+    }
+
+    /// Consumes the driver and hands back the transport
+    pub fn release(self) -> T {
+        self.transport
+    }
+
+    /// Brings up a single UART, see [`crate::SC16IS752::initialise`]
+    ///
+    /// FIFOs are enabled and reset, the baudrate and line format set and IIR, LSR and MSR
+    /// read to clear pending interrupts. The config is validated before any register is
+    /// written.
+    pub async fn initialise(
+        &mut self,
+        channel: Channel,
+        config: UartConfig,
+    ) -> Result<(), Error<E>> {
+        config.validate().map_err(Error::Config)?;
+
+        let (enable_first, fifo_control_register) = fifo_enable_writes(self.fcr[channel as usize]);
+        if let Some(value) = enable_first {
+            self.transport.write_register(channel, 0x02, value).await?;
+        }
+        self.fcr[channel as usize] = fifo_control_register;
+        self.transport
+            .write_register(channel, 0x02, fifo_control_register)
+            .await?;
+        self.transport
+            .write_register(channel, 0x02, fifo_control_register | 0x06)
+            .await?;
+
+        let modem_control_register = self.transport.read_register(channel, 0x04).await?;
+        let divisor = checked_divisor(
+            self.crystal_hz,
+            prescaler(modem_control_register),
+            config.baud,
+        )
+        .map_err(Error::Config)?;
+        // Only break is kept, a DLAB left set would map DLL/DLH over THR/IER
+        let line_control_register = self.transport.read_register(channel, 0x03).await? & 0x40;
+        self.transport
+            .write_register(channel, 0x03, line_control_register | 0x80)
+            .await?;
+        let [divisor_low, divisor_high] = divisor.to_le_bytes();
+        self.transport
+            .write_register(channel, 0x00, divisor_low)
+            .await?;
+        self.transport
+            .write_register(channel, 0x01, divisor_high)
+            .await?;
+        self.transport
+            .write_register(
+                channel,
+                0x03,
+                line_control_register
+                    | line_format(config.word_length, config.parity, config.stop_bit),
+            )
+            .await?;
+
+        self.transport.read_register(channel, 0x02).await?;
+        self.transport.read_register(channel, 0x05).await?;
+        self.transport.read_register(channel, 0x06).await?;
+        Ok(())
+    }
+
+    /// Fills as much of `buf` as the RX FIFO holds in one block read, returning the count
+    ///
+    /// Does not wait for data, `Ok(0)` means nothing has been received.
+    pub async fn read_bulk(&mut self, channel: Channel, buf: &mut [u8]) -> Result<usize, Error<E>> {
+        let available = if self.fcr[channel as usize] & 0x01 != 0 {
+            self.transport.read_register(channel, 0x09).await?
+        } else {
+            self.transport.read_register(channel, 0x05).await? & 0x01
+        };
+        let count = (available as usize).min(buf.len());
+        if count > 0 {
+            self.transport
+                .read_registers(channel, 0x00, &mut buf[..count])
+                .await?;
+        }
+        Ok(count)
+    }
+
+    /// Writes as much of `data` as the TX FIFO has room for in one block write, returning
+    /// the count
+    ///
+    /// Does not wait for space, `Ok(0)` means the FIFO is full.
+    pub async fn write_bulk(&mut self, channel: Channel, data: &[u8]) -> Result<usize, Error<E>> {
+        let space = (self.transport.read_register(channel, 0x08).await? as usize)
+            .min(FIFO_DEPTH)
+            .min(data.len());
+        if space > 0 {
+            self.transport
+                .write_registers(channel, 0x00, &data[..space])
+                .await?;
+        }
+        Ok(space)
+    }
+
+    /// Waits for THR (the TX FIFO in FIFO mode) to empty, yielding between LSR polls
+    ///
+    /// Gives up with [`Error::Timeout`] after 100000 polls, like the blocking `flush`.
+    pub async fn flush(&mut self, channel: Channel) -> Result<(), Error<E>> {
+        for _ in 0..THR_EMPTY_POLLS {
+            if self.transport.read_register(channel, 0x05).await? & 0x20 != 0 {
+                return Ok(());
+            }
+            YieldNow(false).await;
+        }
+        Err(Error::Timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::task::Waker;
+
+    #[derive(Debug, PartialEq)]
+    struct MockError;
+
+    /// Register file per channel with RHR/THR backed by FIFOs. LSR reads pop `lsr` while it
+    /// has values queued, reporting THR and TSR empty otherwise.
+    #[derive(Debug, Default)]
+    struct MockAsyncI2c {
+        regs: [[u8; 16]; 2],
+        rx: [VecDeque<u8>; 2],
+        tx: [Vec<u8>; 2],
+        lsr: VecDeque<u8>,
+        lsr_reads: usize,
+        writes: Vec<(usize, u8, u8)>,
+    }
+
+    impl AsyncTransport for MockAsyncI2c {
+        type Error = MockError;
+
+        async fn read_register(&mut self, channel: Channel, reg: u8) -> Result<u8, MockError> {
+            let channel = channel as usize;
+            Ok(match reg {
+                0x00 => self.rx[channel].pop_front().unwrap_or(0),
+                0x05 => {
+                    self.lsr_reads += 1;
+                    self.lsr.pop_front().unwrap_or(0x60)
+                }
+                0x09 => self.rx[channel].len() as u8,
+                _ => self.regs[channel][reg as usize],
+            })
+        }
+
+        async fn write_register(
+            &mut self,
+            channel: Channel,
+            reg: u8,
+            value: u8,
+        ) -> Result<(), MockError> {
+            let channel = channel as usize;
+            self.writes.push((channel, reg, value));
+            // DLL is mapped over THR while LCR[7] is set
+            match reg {
+                0x00 if self.regs[channel][0x03] & 0x80 == 0 => self.tx[channel].push(value),
+                _ => self.regs[channel][reg as usize] = value,
+            }
+            Ok(())
+        }
+    }
+
+    /// Polls `future` to completion, counting how often it yielded
+    fn block_on<F: Future>(future: F) -> (F::Output, usize) {
+        let mut future = core::pin::pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut pending = 0;
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return (output, pending),
+                Poll::Pending => pending += 1,
+            }
+        }
+    }
+
+    #[test]
+    fn initialise_writes_the_blocking_sequence() {
+        let mut device = AsyncSC16IS752::new(MockAsyncI2c::default());
+        let (result, _) =
+            block_on(device.initialise(Channel::A, UartConfig::default().baudrate(9600)));
+        result.unwrap();
+
+        assert_eq!(
+            device.transport.writes,
+            [
+                (0, 0x02, 0x01),
+                (0, 0x02, 0x07),
+                (0, 0x03, 0x80),
+                (0, 0x00, 0x0C),
+                (0, 0x01, 0x00),
+                (0, 0x03, 0x03),
+            ]
+        );
+    }
+
+    #[test]
+    fn initialise_clears_a_stale_divisor_latch() {
+        let mut transport = MockAsyncI2c::default();
+        transport.regs[0][0x03] = 0x83;
+        let mut device = AsyncSC16IS752::new(transport);
+        block_on(device.initialise(Channel::A, UartConfig::default()))
+            .0
+            .unwrap();
+
+        let last_lcr = device
+            .transport
+            .writes
+            .iter()
+            .rev()
+            .find(|(_, reg, _)| *reg == 0x03);
+        assert_eq!(last_lcr, Some(&(0, 0x03, 0x03)));
+    }
+
+    #[test]
+    fn bulk_read_and_write_use_fifo_levels() {
+        let mut transport = MockAsyncI2c::default();
+        transport.regs[1][0x08] = 3;
+        transport.rx[1].extend([1, 2, 3, 4, 5]);
+        let mut device = AsyncSC16IS752::new(transport);
+        block_on(device.initialise(Channel::B, UartConfig::default()))
+            .0
+            .unwrap();
+
+        let mut buf = [0; 4];
+        assert_eq!(block_on(device.read_bulk(Channel::B, &mut buf)).0, Ok(4));
+        assert_eq!(buf, [1, 2, 3, 4]);
+        assert_eq!(block_on(device.write_bulk(Channel::B, b"hello")).0, Ok(3));
+        assert_eq!(device.transport.tx[1], b"hel");
+    }
+
+    #[test]
+    fn flush_yields_between_lsr_polls() {
+        let mut transport = MockAsyncI2c::default();
+        transport.lsr.extend([0x00, 0x00, 0x20]);
+        let mut device = AsyncSC16IS752::new(transport);
+
+        let (result, yields) = block_on(device.flush(Channel::A));
+        result.unwrap();
+        assert_eq!(yields, 2);
+        assert_eq!(device.transport.lsr_reads, 3);
+    }
+}
@@ -0,0 +1,142 @@
+//! A single UART of the SC16IS752 as a byte stream
+//!
+//! [`UartHandle`] borrows the driver for one channel and implements the `embedded_io`
+//! [`Read`], [`Write`], [`ReadReady`] and [`WriteReady`] traits, so code written against
+//! generic serial traits can be pointed at one of the chip's UARTs. It also implements
+//! [`core::fmt::Write`] for formatted output with `write!`.
+
+use crate::{Channel, Error, Transport, SC16IS752, THR_EMPTY_POLLS};
+use embedded_io::{ErrorType, Read, ReadReady, Write, WriteReady};
+
+/// One channel of an [`SC16IS752`], returned by [`SC16IS752::channel`]
+#[derive(Debug)]
+pub struct UartHandle<'a, T: Transport> {
+    device: &'a mut SC16IS752<T>,
+    channel: Channel,
+    last_error: Option<Error<T::Error>>,
+}
+
+impl<'a, T, E> UartHandle<'a, T>
+where
+    T: Transport<Error = E>,
+{
+    pub(crate) fn new(device: &'a mut SC16IS752<T>, channel: Channel) -> Self {
+        Self {
+            device,
+            channel,
+            last_error: None,
+        }
+    }
+
+    /// The channel this handle talks to
+    pub fn channel(&self) -> Channel {
+        self.channel
+    }
+
+    /// Waits for at least one received byte, then drains up to `buf.len()` bytes from the RX
+    /// FIFO and returns how many were read
+    ///
+    /// Waits as long as it takes for data to arrive, check `read_ready` first to avoid
+    /// blocking. Returns `Ok(0)` only for an empty `buf`.
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error<E>> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let count = self.device.read_bulk(self.channel, buf)?;
+            if count > 0 {
+                return Ok(count);
+            }
+        }
+    }
+
+    /// Whether `read` would return without waiting
+    pub fn read_ready(&mut self) -> Result<bool, Error<E>> {
+        Ok(self.device.available_with_peek(self.channel)? > 0)
+    }
+
+    /// Pushes as many bytes of `buf` as the TX FIFO has room for, returning the count
+    ///
+    /// If the FIFO is full this waits for THR to empty first, so at least one byte is written
+    /// unless `buf` is empty. Gives up with [`Error::Timeout`] after the same number of polls
+    /// as `SC16IS752::flush`.
+    pub fn write(&mut self, buf: &[u8]) -> Result<usize, Error<E>> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let written = self.device.write_bulk(self.channel, buf)?;
+        if written > 0 {
+            return Ok(written);
+        }
+        self.device.flush_timeout(self.channel, THR_EMPTY_POLLS)?;
+        self.device.write_bulk(self.channel, buf)
+    }
+
+    /// Whether `write` would accept a byte without waiting
+    pub fn write_ready(&mut self) -> Result<bool, Error<E>> {
+        Ok(self.device.fifo_available_space(self.channel)? > 0)
+    }
+
+    /// Blocks until the TX FIFO and the transmit shift register are both empty
+    ///
+    /// Gives up with [`Error::Timeout`] after 100000 LSR polls, e.g. if the transmitter is
+    /// held off by flow control that never releases.
+    pub fn flush(&mut self) -> Result<(), Error<E>> {
+        self.device.wait_transmitter_empty(self.channel)
+    }
+
+    /// The driver error behind the last [`core::fmt::Error`] returned by `write_str`
+    pub fn last_error(&self) -> Option<Error<E>>
+    where
+        E: Copy,
+    {
+        self.last_error
+    }
+}
+
+impl<'a, T: Transport> ErrorType for UartHandle<'a, T> {
+    type Error = Error<T::Error>;
+}
+
+impl<'a, T: Transport> Read for UartHandle<'a, T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error<T::Error>> {
+        UartHandle::read(self, buf)
+    }
+}
+
+impl<'a, T: Transport> ReadReady for UartHandle<'a, T> {
+    fn read_ready(&mut self) -> Result<bool, Error<T::Error>> {
+        UartHandle::read_ready(self)
+    }
+}
+
+impl<'a, T: Transport> Write for UartHandle<'a, T> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error<T::Error>> {
+        UartHandle::write(self, buf)
+    }
+
+    fn flush(&mut self) -> Result<(), Error<T::Error>> {
+        UartHandle::flush(self)
+    }
+}
+
+impl<'a, T: Transport> WriteReady for UartHandle<'a, T> {
+    fn write_ready(&mut self) -> Result<bool, Error<T::Error>> {
+        UartHandle::write_ready(self)
+    }
+}
+
+impl<'a, T, E> core::fmt::Write for UartHandle<'a, T>
+where
+    T: Transport<Error = E>,
+{
+    /// Blocks until the whole string is in the TX FIFO
+    ///
+    /// A bus error or THR timeout is kept for `last_error` and reported as `fmt::Error`.
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        Write::write_all(self, s.as_bytes()).map_err(|error| {
+            self.last_error = Some(error);
+            core::fmt::Error
+        })
+    }
+}
@@ -1,26 +1,20 @@
 //!
 //! # Example
 //!
-//! ```
-//!
-//! let mut device = SC16IS752::new(SC16IS750_ADDRESS, i2c)?;
-//! device.initalise(Channel::A, UartConfig::default().baudrate(9600))?;
+//! ```no_run
+//! # use sc16is752::{Channel, Error, PinMode, PinState, UartConfig, GPIO, SC16IS752};
+//! # use std::{thread, time::Duration};
+//! # fn run<I2C: embedded_hal::i2c::blocking::I2c>(i2c: I2C) -> Result<(), Error<I2C::Error>> {
+//! let mut device = SC16IS752::new(0x48, i2c)?;
+//! device.initialise(Channel::A, UartConfig::default().baudrate(9600))?;
 //! device.gpio_set_pin_mode(GPIO::GPIO0, PinMode::Output)?;
 //! device.flush(Channel::A)?;
 //! loop {
-//!     device.write_byte(Channel::A, "a".as_bytes()[0])?;
-//!     println!("RX A = {:?}", device.read_byte(Channel::A)?);
+//!     device.write(Channel::A, b"a")?;
+//!     println!("RX A = {:?}", device.read(Channel::A, 1)?);
 //!
-//!     for byte in b"This is channel A" {
-//!         device.write_byte(Channel::A, byte)?;
-//!     }
-//!     let mut buf_a: Vec<u8> = vec![];
-//!     for _ in 0..device.fifo_available_data(Channel::A)? {
-//!         match device.read_byte(Channel::A)? {
-//!             Some(byte) => buf_a.push(byte),
-//!             None => break,
-//!         }
-//!     }
+//!     device.write(Channel::A, b"This is channel A")?;
+//!     let buf_a: Vec<u8> = device.rx_iter(Channel::A).collect::<Result<_, _>>()?;
 //!     println!("RX UART A = {}", String::from_utf8_lossy(&buf_a));
 //!
 //!     thread::sleep(Duration::from_millis(250));
@@ -37,14 +31,114 @@
 //!         "GPIO0 = {:?} (Output toggled)",
 //!         device.gpio_get_pin_state(GPIO::GPIO0)?
 //!     );
+//! }
+//! # }
 //! ```
 
-use embedded_hal::i2c::{blocking::I2c, Error};
+use core::time::Duration;
+use embedded_hal::delay::blocking::DelayUs;
+use embedded_hal::i2c::{blocking::I2c, Error as I2cError};
+use embedded_hal::spi::blocking::{SpiBus, SpiDevice};
 
+#[cfg(feature = "async")]
+mod asynch;
+mod handle;
+mod transport;
+#[cfg(feature = "async")]
+pub use asynch::{AsyncSC16IS752, AsyncTransport};
+pub use handle::UartHandle;
+pub use transport::{I2cTransport, SpiTransport, Transport};
+
+/// Crystal fitted to most boards, used unless `new_with_crystal` says otherwise
 const CRYSTAL_FREQ: u32 = 1843200;
+/// Standard rates reported by `supported_baud_rates`
+const STANDARD_BAUD_RATES: [u32; 11] = [
+    300, 600, 1200, 2400, 4800, 9600, 14400, 19200, 38400, 57600, 115200,
+];
+/// Size of each RX and TX FIFO
+const FIFO_DEPTH: usize = 64;
+/// Delay between register polls in the methods taking a timeout
+const POLL_INTERVAL_US: u32 = 100;
+/// Number of polls made while waiting for a FIFO reset to complete
+const FIFO_RESET_POLLS: u8 = 10;
+/// How long `loopback_pattern_test` waits for looped back data to stop arriving
+const LOOPBACK_TIMEOUT: Duration = Duration::from_millis(100);
+/// Number of 100 µs polls `send` waits for TX FIFO space before giving up
+const TX_STALL_POLLS: u32 = 1000;
+/// LSR polls `flush` and `write` make waiting for THR to empty before giving up
+const THR_EMPTY_POLLS: u32 = 100_000;
+/// Scratchpad pings `reset_device` makes waiting for the chip to come back out of reset
+const RESET_PINGS: u32 = 100;
+/// First backoff delay of `recover_bus`, doubled after every failed attempt
+const RECOVER_BACKOFF_US: u32 = 1000;
+
+/// Errors returned by the driver
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Error<E> {
+    /// Error from the underlying I2C or SPI bus
+    Bus(E),
+    /// A configuration value was out of range
+    Config(ConfigError),
+    /// The device kept failing to respond, the bus or chip likely needs a power cycle
+    BusWedged,
+    /// A bounded wait on the device ran out
+    Timeout,
+    /// The configured chip variant does not have this feature
+    UnsupportedFeature,
+    /// Configuration read back from the chip differs from what the driver wrote
+    Mismatch,
+    /// The channel still had data to transmit
+    TxNotEmpty,
+}
+impl<E> From<E> for Error<E> {
+    fn from(error: E) -> Self {
+        Error::Bus(error)
+    }
+}
+impl<E: core::fmt::Debug> core::fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+impl<E: core::fmt::Debug> embedded_io::Error for Error<E> {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        match self {
+            Error::Config(_) => embedded_io::ErrorKind::InvalidInput,
+            Error::Timeout => embedded_io::ErrorKind::TimedOut,
+            Error::UnsupportedFeature => embedded_io::ErrorKind::Unsupported,
+            _ => embedded_io::ErrorKind::Other,
+        }
+    }
+}
+
+/// Rejected configuration values
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ConfigError {
+    /// FIFO trigger levels are 1 to 15, in units of 4 bytes
+    InvalidTriggerLevel,
+    /// I2C chunk sizes are 1 to 64 bytes
+    InvalidChunkSize,
+    /// Flow control thresholds are multiples of 4 up to 60 bytes, with halt above resume
+    InvalidFlowThreshold,
+    /// Register addresses are 0x00 to 0x0F
+    InvalidRegister,
+    /// The baudrate needs a divisor below 1, too fast for the crystal and prescaler
+    BaudrateTooHigh,
+    /// The baudrate needs a divisor above 0xFFFF, or is 0
+    BaudrateTooLow,
+    /// Word lengths are 5 to 8 bits
+    InvalidWordLength,
+    /// Stop bits are 1 or 2
+    InvalidStopBits,
+}
+impl core::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
 
 /// UARTs Channel A (TXA/RXA) and Channel B (TXB/RXB)
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Channel {
     /// UART A
     A,
@@ -59,7 +153,7 @@ impl core::fmt::Display for Channel {
 
 /// GPIO pins 0-7
 #[allow(missing_docs)]
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum GPIO {
     GPIO0,
     GPIO1,
@@ -76,8 +170,22 @@ impl core::fmt::Display for GPIO {
     }
 }
 
+/// Which GPIO pins are free to use given the modem pin muxing in IOControl
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GpioAvailability {
+    /// Bit n set when GPIOn can be used as a GPIO
+    pub available: u8,
+}
+
+impl GpioAvailability {
+    /// Whether `pin` can be used as a GPIO
+    pub fn is_available(&self, pin: GPIO) -> bool {
+        self.available & (1 << pin as u8) != 0
+    }
+}
+
 #[allow(missing_docs)]
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum PinMode {
     Input,
     Output,
@@ -88,7 +196,39 @@ impl core::fmt::Display for PinMode {
     }
 }
 
-#[derive(Debug)]
+/// GPIO input pull resistor configuration
+#[allow(missing_docs)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Pull {
+    None,
+    Up,
+    Down,
+}
+impl core::fmt::Display for Pull {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Divider between the crystal and the baudrate generator, MCR bit 7
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Prescaler {
+    /// Crystal used as is (reset default)
+    DivideBy1,
+    /// Crystal divided by 4
+    DivideBy4,
+}
+impl Prescaler {
+    /// The divider value
+    fn divider(self) -> u32 {
+        match self {
+            Prescaler::DivideBy1 => 1,
+            Prescaler::DivideBy4 => 4,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum PinState {
     Low,
     High,
@@ -99,8 +239,10 @@ impl core::fmt::Display for PinState {
     }
 }
 
+/// Interrupt source decoded from IIR by `next_interrupt` and `isr`
 #[allow(non_camel_case_types)]
-pub enum InterruptEventTest {
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InterruptEvent {
     RECEIVE_LINE_STATUS_ERROR,
     RECEIVE_TIMEOUT_INTERRUPT,
     RHR_INTERRUPT,
@@ -111,6 +253,10 @@ pub enum InterruptEventTest {
     CTS_RTS_CHANGE,
     UNKNOWN,
 }
+#[deprecated(note = "renamed to InterruptEvent")]
+#[allow(missing_docs)]
+pub type InterruptEventTest = InterruptEvent;
+
 /// Extra Features Control Register options
 #[derive()]
 pub enum FeaturesRegister {
@@ -141,7 +287,261 @@ pub enum FeaturesRegister {
     Multidrop = 0x01,
 }
 
+/// Members of the SC16IS7xx family
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Variant {
+    /// Single UART
+    SC16IS750,
+    /// Dual UART
+    SC16IS752,
+    /// Single UART with IrDA fast mode
+    SC16IS760,
+    /// Dual UART with IrDA fast mode
+    SC16IS762,
+}
+impl Variant {
+    /// Whether the part has a second UART (Channel B)
+    pub fn is_dual_channel(&self) -> bool {
+        matches!(self, Variant::SC16IS752 | Variant::SC16IS762)
+    }
+}
+impl core::fmt::Display for Variant {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Decoded state of the Extra Features Control Register, see [`FeaturesRegister`]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct FeaturesState {
+    /// IrDA SIR 1⁄4 pulse ratio (SC16IS762 only)
+    pub irda_fast: bool,
+    /// RTS inverted in RS-485 mode
+    pub auto_rs485_rts_output_inversion: bool,
+    /// Transmitter controls the RTS pin
+    pub auto_rs485_direction_control: bool,
+    /// Transmitter disabled
+    pub tx_disable: bool,
+    /// Receiver disabled
+    pub rx_disable: bool,
+    /// 9-bit/Multidrop mode enabled
+    pub multidrop: bool,
+}
+
+impl From<u8> for FeaturesState {
+    fn from(extra_features_control_register: u8) -> Self {
+        let is_set =
+            |feature: FeaturesRegister| extra_features_control_register & feature as u8 != 0;
+        Self {
+            irda_fast: is_set(FeaturesRegister::IrDaFast),
+            auto_rs485_rts_output_inversion: is_set(FeaturesRegister::AutoRs485RTSOutputInversion),
+            auto_rs485_direction_control: is_set(FeaturesRegister::AutoRs485DirectionControl),
+            tx_disable: is_set(FeaturesRegister::TxDisable),
+            rx_disable: is_set(FeaturesRegister::RxDisable),
+            multidrop: is_set(FeaturesRegister::Multidrop),
+        }
+    }
+}
+
+/// A single register access, passed to the hook set with `set_trace`
+///
+/// Block reads and writes of RHR/THR report one event per byte.
+#[cfg(feature = "trace")]
+#[allow(missing_docs)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TraceEvent {
+    Read {
+        channel: Channel,
+        reg: u8,
+        value: u8,
+    },
+    Write {
+        channel: Channel,
+        reg: u8,
+        value: u8,
+    },
+}
+
+/// Result of `benchmark_channel`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ThroughputReport {
+    /// Bytes written to the TX FIFO
+    pub bytes_sent: usize,
+    /// Bytes read back from the RX FIFO
+    pub bytes_received: usize,
+    /// Bytes that came back different from what was sent, or never came back
+    pub errors: usize,
+    /// Time between the first write and the last read
+    pub elapsed: Duration,
+    /// Bytes received per second over `elapsed`
+    pub bytes_per_sec: u32,
+}
+
+/// Register bank snapshot from `dump_registers`, labelled by register name when debug printed
+#[derive(Copy, Clone, PartialEq)]
+pub struct RegisterDump(pub [u8; 16]);
+impl RegisterDump {
+    /// General register set names of 0x00 to 0x0F
+    const NAMES: [&'static str; 16] = [
+        "RHR",
+        "IER",
+        "IIR",
+        "LCR",
+        "MCR",
+        "LSR",
+        "MSR",
+        "SPR",
+        "TXLVL",
+        "RXLVL",
+        "IODir",
+        "IOState",
+        "IOIntEna",
+        "reserved",
+        "IOControl",
+        "EFCR",
+    ];
+}
+impl From<[u8; 16]> for RegisterDump {
+    fn from(registers: [u8; 16]) -> Self {
+        Self(registers)
+    }
+}
+impl core::fmt::Debug for RegisterDump {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> std::fmt::Result {
+        let mut dump = f.debug_struct("RegisterDump");
+        for (name, value) in Self::NAMES.iter().zip(self.0).skip(1) {
+            dump.field(name, &format_args!("{value:#04x}"));
+        }
+        dump.field("RHR", &format_args!("not read")).finish()
+    }
+}
+
+/// Decoded Line Status Register
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LineStatus {
+    /// At least one character in the RX FIFO
+    pub data_ready: bool,
+    /// A character arrived with the RX FIFO full and was lost
+    pub overrun_error: bool,
+    /// Parity error in the character at the top of the RX FIFO
+    pub parity_error: bool,
+    /// Framing error (no valid stop bit) in the character at the top of the RX FIFO
+    pub framing_error: bool,
+    /// Break condition on the RX line, reported with the character at the top of the RX FIFO
+    pub break_interrupt: bool,
+    /// A parity, framing or break error is in one of the characters in the RX FIFO
+    pub fifo_data_error: bool,
+    /// THR (the TX FIFO in FIFO mode) is empty
+    pub thr_empty: bool,
+    /// THR and the transmit shift register are both empty
+    pub transmitter_empty: bool,
+}
+
+impl From<u8> for LineStatus {
+    fn from(line_status_register: u8) -> Self {
+        let is_set = |bit: u8| line_status_register & (1 << bit) != 0;
+        Self {
+            data_ready: is_set(0),
+            overrun_error: is_set(1),
+            parity_error: is_set(2),
+            framing_error: is_set(3),
+            break_interrupt: is_set(4),
+            thr_empty: is_set(5),
+            transmitter_empty: is_set(6),
+            fifo_data_error: is_set(7),
+        }
+    }
+}
+
+/// Decoded Modem Status Register, each input true when asserted (pin low)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ModemStatus {
+    /// Clear to send
+    pub cts: bool,
+    /// Data set ready
+    pub dsr: bool,
+    /// Ring indicator
+    pub ri: bool,
+    /// Carrier detect
+    pub cd: bool,
+}
+
+impl From<u8> for ModemStatus {
+    fn from(modem_status_register: u8) -> Self {
+        Self {
+            cts: modem_status_register & 0x10 != 0,
+            dsr: modem_status_register & 0x20 != 0,
+            ri: modem_status_register & 0x40 != 0,
+            cd: modem_status_register & 0x80 != 0,
+        }
+    }
+}
+
+/// Receive error attached to a single character by `read_byte_checked`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LineError {
+    /// Nothing has been received
+    Empty,
+    /// The character failed the parity check
+    Parity(u8),
+    /// The character had no valid stop bit
+    Framing(u8),
+    /// A break condition was received in place of a character
+    Break,
+}
+
+/// Software (XON/XOFF) flow control modes, EFR bits 3:0
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SwFlowMode {
+    /// No software flow control
+    None,
+    /// Transmit and compare XON1/XOFF1
+    XonXoff1,
+    /// Transmit and compare XON2/XOFF2
+    XonXoff2,
+    /// Transmit and compare XON1 + XON2 / XOFF1 + XOFF2 as two byte sequences
+    XonXoff1And2,
+    /// Any other combination of the EFR[3:0] transmit/receive control bits
+    Other(u8),
+}
+impl SwFlowMode {
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0x0F {
+            0x00 => SwFlowMode::None,
+            0x0A => SwFlowMode::XonXoff1,
+            0x05 => SwFlowMode::XonXoff2,
+            0x0F => SwFlowMode::XonXoff1And2,
+            other => SwFlowMode::Other(other),
+        }
+    }
+
+    fn bits(&self) -> u8 {
+        match self {
+            SwFlowMode::None => 0x00,
+            SwFlowMode::XonXoff1 => 0x0A,
+            SwFlowMode::XonXoff2 => 0x05,
+            SwFlowMode::XonXoff1And2 => 0x0F,
+            SwFlowMode::Other(bits) => bits & 0x0F,
+        }
+    }
+}
+impl core::fmt::Display for SwFlowMode {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Selects the receive FIFO, the transmit FIFO or both
 #[allow(missing_docs)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FifoSelect {
+    Rx,
+    Tx,
+    Both,
+}
+
+#[allow(missing_docs)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Parity {
     NoParity,
     Odd,
@@ -151,6 +551,7 @@ pub enum Parity {
 }
 
 #[allow(missing_docs)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct UartConfig {
     baud: u32,
     word_length: u8,
@@ -171,7 +572,7 @@ impl UartConfig {
             word_length,
             // Use Parity enum
             parity,
-            // Use 0, 1 or 2 (insert table here)
+            // 1 or 2, 2 gives 1.5 stop bits with 5 bit words
             stop_bit,
         }
     }
@@ -179,6 +580,46 @@ impl UartConfig {
         self.baud = baud;
         self
     }
+    pub fn word_length(mut self, word_length: u8) -> Self {
+        self.word_length = word_length;
+        self
+    }
+    pub fn parity(mut self, parity: Parity) -> Self {
+        self.parity = parity;
+        self
+    }
+    pub fn stop_bit(mut self, stop_bit: u8) -> Self {
+        self.stop_bit = stop_bit;
+        self
+    }
+
+    /// Bits on the wire per character: start, data, parity and stop bits
+    ///
+    /// 1.5 stop bits (5 bit words with `stop_bit` 2) are counted as 2.
+    pub fn bits_per_char(&self) -> u32 {
+        let parity_bits = match self.parity {
+            Parity::NoParity => 0,
+            _ => 1,
+        };
+        1 + self.word_length as u32 + parity_bits + self.stop_bit as u32
+    }
+
+    /// How long `chars` characters take to send at the configured baudrate
+    pub fn char_time(&self, chars: u32) -> Duration {
+        let bits = (chars * self.bits_per_char()) as u64;
+        Duration::from_nanos(bits * 1_000_000_000 / self.baud.max(1) as u64)
+    }
+
+    /// Checks the word length is 5 to 8 bits and the stop bits 1 or 2
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !(5..=8).contains(&self.word_length) {
+            return Err(ConfigError::InvalidWordLength);
+        }
+        if !(1..=2).contains(&self.stop_bit) {
+            return Err(ConfigError::InvalidStopBits);
+        }
+        Ok(())
+    }
 }
 
 impl Default for UartConfig {
@@ -193,84 +634,344 @@ impl Default for UartConfig {
 }
 
 #[derive(Debug)]
-pub struct SC16IS752<I2C> {
-    address: u8,
-    i2c: I2C,
+pub struct SC16IS752<T> {
+    transport: T,
     fifo: [u8; 2],
     peek_flags: [bool; 2],
     peek_buf: [Option<u8>; 2],
+    max_i2c_chunk: usize,
+    variant: Variant,
+    fcr: [u8; 2],
+    baud_change_fifo_reset: bool,
+    last_interrupt: [Option<InterruptEvent>; 2],
+    bytes_transmitted: [u64; 2],
+    crystal_hz: u32,
+    config: [Option<UartConfig>; 2],
+    tlr: [u8; 2],
+    prescaler: [Prescaler; 2],
+    #[cfg(feature = "trace")]
+    trace: Option<fn(TraceEvent)>,
 }
 
-impl<I2C, E: Error> SC16IS752<I2C>
+/// The driver over I2C, the type `SC16IS752<I2C>` named before the transport was made generic
+pub type SC16IS752I2c<I2C> = SC16IS752<I2cTransport<I2C>>;
+
+/// The driver over SPI, as built by `SC16IS752::new_spi`
+pub type SC16IS752Spi<SPI> = SC16IS752<SpiTransport<SPI>>;
+
+impl<I2C, E: I2cError> SC16IS752<I2cTransport<I2C>>
 where
     I2C: I2c<Error = E>,
 {
-    pub fn new(device_address: u8, i2c: I2C) -> Result<Self, E> {
-        let mut address = device_address;
-        if !(0x48..=0x57).contains(&device_address) {
-            address = device_address >> 1
-        }
-        Ok(Self {
-            address,
-            i2c,
+    /// Driver for a chip at `device_address` on the I2C bus
+    ///
+    /// No register is touched here, so the driver starts out assuming the FIFOs are disabled
+    /// and reads RHR a byte at a time through LSR data ready. Call `initialise` or
+    /// `fifo_enable` before the bulk read paths, also when taking over a chip whose FIFOs
+    /// were already enabled, e.g. after `release`.
+    pub fn new(device_address: u8, i2c: I2C) -> Result<Self, Error<E>> {
+        Self::new_with_crystal(device_address, i2c, CRYSTAL_FREQ)
+    }
+
+    /// Like `new` for boards fitted with a crystal other than 1.8432 MHz, e.g. 14.7456 MHz
+    pub fn new_with_crystal(
+        device_address: u8,
+        i2c: I2C,
+        crystal_hz: u32,
+    ) -> Result<Self, Error<E>> {
+        let mut device = Self::with_transport(I2cTransport::new(device_address, i2c));
+        device.crystal_hz = crystal_hz;
+        Ok(device)
+    }
+
+    /// Consumes the driver and hands back the I2C bus, e.g. to share it with another device
+    pub fn release(self) -> I2C {
+        self.transport.i2c
+    }
+}
+
+impl<SPI> SC16IS752<SpiTransport<SPI>>
+where
+    SPI: SpiDevice,
+    SPI::Bus: SpiBus,
+{
+    /// Driver for a chip wired up over SPI
+    ///
+    /// As with `new`, the FIFOs are assumed disabled until `initialise` or `fifo_enable`.
+    pub fn new_spi(spi: SPI) -> Self {
+        Self::with_transport(SpiTransport::new(spi))
+    }
+
+    /// Consumes the driver and hands back the SPI device
+    pub fn release(self) -> SPI {
+        self.transport.spi
+    }
+}
+
+impl<T, E> SC16IS752<T>
+where
+    T: Transport<Error = E>,
+{
+    /// Driver over any [`Transport`], `new` and `new_spi` cover the I2C and SPI cases
+    pub fn with_transport(transport: T) -> Self {
+        Self {
+            transport,
             fifo: [0u8; 2],
             peek_flags: [false; 2],
             peek_buf: [None; 2],
-        })
+            max_i2c_chunk: FIFO_DEPTH,
+            variant: Variant::SC16IS752,
+            fcr: [0u8; 2],
+            baud_change_fifo_reset: false,
+            last_interrupt: [None; 2],
+            bytes_transmitted: [0; 2],
+            crystal_hz: CRYSTAL_FREQ,
+            config: [None; 2],
+            tlr: [0u8; 2],
+            prescaler: [Prescaler::DivideBy1; 2],
+            #[cfg(feature = "trace")]
+            trace: None,
+        }
+    }
+
+    /// Sets which member of the SC16IS7xx family the driver is talking to (default SC16IS752)
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+    }
+
+    /// The configured chip variant
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// Borrows a single UART as a [`UartHandle`] byte stream
+    pub fn channel(&mut self, channel: Channel) -> UartHandle<'_, T> {
+        UartHandle::new(self, channel)
     }
 
-    /// Initalises a single UART using UartConfig struct
-    pub fn initalise_uart(&mut self, channel: Channel, config: UartConfig) -> Result<(), E> {
+    /// Brings up a single UART using UartConfig struct
+    ///
+    /// The config is validated before any register is written. The FIFOs are then enabled and
+    /// reset, the baudrate and line format set, and IIR, LSR and MSR read to clear any
+    /// interrupt left pending from before.
+    pub fn initialise(&mut self, channel: Channel, config: UartConfig) -> Result<(), Error<E>> {
+        config.validate().map_err(Error::Config)?;
         self.fifo_enable(channel, true)?;
+        self.fifo_reset(channel, true, true)?;
         self.set_baudrate(channel, config.baud)?;
         self.set_line(channel, config.word_length, config.parity, config.stop_bit)?;
+        self.read_register(channel, 0x02)?;
+        self.read_register(channel, 0x05)?;
+        self.read_register(channel, 0x06)?;
+        self.config[channel as usize] = Some(config);
         Ok(())
     }
 
-    fn read_register(&mut self, channel: Channel, reg_address: u8) -> Result<u8, E> {
-        let mut result = [0];
-        self.i2c
-            .write_read(
-                self.address,
-                &[reg_address << 3 | (channel as u8) << 1],
-                &mut result,
-            )
-            .and(Ok(result[0]))
+    /// Misspelt former name of `initialise`
+    #[deprecated(note = "renamed to initialise")]
+    pub fn initalise_uart(&mut self, channel: Channel, config: UartConfig) -> Result<(), Error<E>> {
+        self.initialise(channel, config)
     }
 
-    fn write_register(&mut self, channel: Channel, reg_address: u8, payload: u8) -> Result<(), E> {
-        self.i2c.write(
-            self.address,
-            &[reg_address << 3 | (channel as u8) << 1u8, payload],
-        )
+    /// The channels set up by `initialise` and not since closed or reset
+    pub fn configured_channels(&self) -> impl Iterator<Item = Channel> + '_ {
+        [Channel::A, Channel::B]
+            .into_iter()
+            .filter(|channel| self.config[*channel as usize].is_some())
     }
 
-    fn set_baudrate(&mut self, channel: Channel, baudrate: u32) -> Result<(), E> {
-        let prescaler = match self.read_register(channel, 0x04)? {
-            0 => 1,
-            _ => 4,
-        };
-        let divisor = (CRYSTAL_FREQ / prescaler as u32) / (baudrate * 16);
+    /// Shuts a channel down cleanly, the counterpart of `initialise`
+    ///
+    /// Disables all of the channel's interrupts, waits for pending TX data to leave the
+    /// transmitter and resets both FIFOs. With `disable_uart` the transmitter and receiver are
+    /// also switched off through EFCR, `enable_features` turns them back on.
+    ///
+    /// [`Error::Timeout`] if the transmitter doesn't empty within 100000 LSR polls, e.g. held
+    /// off by flow control. Interrupts are left disabled and the FIFOs untouched in that case.
+    pub fn close(&mut self, channel: Channel, disable_uart: bool) -> Result<(), Error<E>> {
+        self.write_register(channel, 0x01, 0x00)?;
+        self.wait_transmitter_empty(channel)?;
+        self.fifo_reset(channel, true, true)?;
+        if disable_uart {
+            self.enable_features(channel, FeaturesRegister::TxDisable, true)?;
+            self.enable_features(channel, FeaturesRegister::RxDisable, true)?;
+        }
+        self.config[channel as usize] = None;
+        Ok(())
+    }
+
+    /// Calls `hook` on every register read and write, `None` turns tracing off
+    #[cfg(feature = "trace")]
+    pub fn set_trace(&mut self, hook: Option<fn(TraceEvent)>) {
+        self.trace = hook;
+    }
+
+    #[cfg(feature = "trace")]
+    fn trace(&self, event: TraceEvent) {
+        if let Some(hook) = self.trace {
+            hook(event);
+        }
+    }
+
+    fn read_register(&mut self, channel: Channel, reg_address: u8) -> Result<u8, Error<E>> {
+        let value = self.transport.read_register(channel, reg_address)?;
+        #[cfg(feature = "trace")]
+        self.trace(TraceEvent::Read {
+            channel,
+            reg: reg_address,
+            value,
+        });
+        Ok(value)
+    }
+
+    fn write_register(
+        &mut self,
+        channel: Channel,
+        reg_address: u8,
+        payload: u8,
+    ) -> Result<(), Error<E>> {
+        self.transport
+            .write_register(channel, reg_address, payload)?;
+        #[cfg(feature = "trace")]
+        self.trace(TraceEvent::Write {
+            channel,
+            reg: reg_address,
+            value: payload,
+        });
+        Ok(())
+    }
+
+    /// Writes a table of (register, value) pairs to a channel, in order
+    ///
+    /// An escape hatch for exact register layouts computed elsewhere, e.g. by a vendor tool.
+    /// The table is written verbatim, so it must include any LCR writes needed to reach the
+    /// special or enhanced register sets. Every address is checked before anything is written.
+    pub fn apply_register_table(
+        &mut self,
+        channel: Channel,
+        table: &[(u8, u8)],
+    ) -> Result<(), Error<E>> {
+        if table.iter().any(|(reg, _)| *reg > 0x0F) {
+            return Err(Error::Config(ConfigError::InvalidRegister));
+        }
+        for (reg, value) in table {
+            self.write_register(channel, *reg, *value)?;
+        }
+        Ok(())
+    }
+
+    /// Caps the number of FIFO bytes moved per I2C transaction by the burst read/write paths
+    ///
+    /// Defaults to the 64 byte FIFO depth, lower it for I2C controllers with a maximum
+    /// transfer length.
+    pub fn set_max_i2c_chunk(&mut self, max_i2c_chunk: usize) -> Result<(), ConfigError> {
+        if !(1..=FIFO_DEPTH).contains(&max_i2c_chunk) {
+            return Err(ConfigError::InvalidChunkSize);
+        }
+        self.max_i2c_chunk = max_i2c_chunk;
+        Ok(())
+    }
+
+    fn set_baudrate(&mut self, channel: Channel, baudrate: u32) -> Result<(), Error<E>> {
+        let prescaler = prescaler(self.read_register(channel, 0x04)?);
+        let divisor =
+            checked_divisor(self.crystal_hz, prescaler, baudrate).map_err(Error::Config)?;
 
         let mut temp_line_control_register = self.read_register(channel, 0x03)?;
         temp_line_control_register |= 0x80;
         self.write_register(channel, 0x03, temp_line_control_register)?;
 
-        self.write_register(channel, 0x00, divisor.try_into().unwrap())?;
-        self.write_register(channel, 0x01, (divisor >> 8).try_into().unwrap())?;
+        let [divisor_low, divisor_high] = divisor.to_le_bytes();
+        self.write_register(channel, 0x00, divisor_low)?;
+        self.write_register(channel, 0x01, divisor_high)?;
 
         temp_line_control_register &= 0x7F;
         self.write_register(channel, 0x03, temp_line_control_register)?;
 
-        // {
-        //     let actual_baudrate = (CRYSTAL_FREQ / prescaler as u32) / (16 * divisor);
-        //     let error = (actual_baudrate - baudrate) * 1000 / baudrate;
+        if self.baud_change_fifo_reset {
+            self.fifo_reset(channel, true, true)?;
+        }
+        Ok(())
+    }
 
-        //     println!("UART {channel}: Desired baudrate: {baudrate}");
-        //     println!("UART {channel}: Calculated divisor: {divisor}");
-        //     println!("UART {channel}: Actual baudrate: {actual_baudrate}");
-        //     println!("UART {channel}: Baudrate error: {error}");
-        // }
+    /// Lists the standard baudrates from 300 to 115200 achievable on `channel` with the
+    /// crystal and the prescaler set by `set_prescaler`, along with the rate error in permille
+    ///
+    /// Rates too high for the crystal are left out.
+    pub fn supported_baud_rates(&self, channel: Channel) -> impl Iterator<Item = (u32, i32)> {
+        let crystal_hz = self.crystal_hz;
+        let prescaler = self.prescaler[channel as usize].divider();
+        STANDARD_BAUD_RATES.into_iter().filter_map(move |baud| {
+            let divisor = compute_divisor(crystal_hz, prescaler, baud);
+            if divisor == 0 {
+                return None;
+            }
+            let actual = actual_baud(crystal_hz, prescaler, divisor) as i64;
+            Some((baud, ((actual - baud as i64) * 1000 / baud as i64) as i32))
+        })
+    }
+
+    /// How far the closest achievable rate is from `baud`, in parts per million
+    ///
+    /// The divisor is rounded down so the actual rate is never below the requested one. Like
+    /// `supported_baud_rates` this uses the channel's prescaler from `set_prescaler`. Check
+    /// this before committing to a rate, UARTs typically tolerate a few percent.
+    pub fn baudrate_error_ppm(&self, channel: Channel, baud: u32) -> Result<i32, ConfigError> {
+        let prescaler = self.prescaler[channel as usize].divider();
+        let divisor = checked_divisor(self.crystal_hz, prescaler, baud)? as u32;
+        let actual = actual_baud(self.crystal_hz, prescaler, divisor) as i64;
+        Ok(((actual - baud as i64) * 1_000_000 / baud as i64) as i32)
+    }
+
+    /// Selects the clock prescaler used by subsequent baudrate changes
+    ///
+    /// MCR[7] is only writable with EFR[4] set, enhanced functions are switched on for the
+    /// write and EFR is restored afterwards. Set the baudrate again afterwards, the divisor is
+    /// not recomputed here.
+    pub fn set_prescaler(
+        &mut self,
+        channel: Channel,
+        prescaler: Prescaler,
+    ) -> Result<(), Error<E>> {
+        self.with_enhanced_functions(channel, |device| {
+            device.set_modem_control_bits(channel, 0x80, prescaler == Prescaler::DivideBy4)
+        })?;
+        self.prescaler[channel as usize] = prescaler;
+        Ok(())
+    }
+
+    /// Uses a measured crystal frequency for subsequent baudrate changes
+    ///
+    /// For boards whose crystal is noticeably off nominal, measured against a known reference.
+    /// Already configured channels keep their divisor until their baudrate is set again.
+    pub fn calibrate_crystal(&mut self, measured_hz: u32) {
+        self.crystal_hz = measured_hz;
+    }
+
+    /// The crystal frequency baudrates are computed from
+    pub fn crystal_hz(&self) -> u32 {
+        self.crystal_hz
+    }
+
+    /// Makes every baudrate change also reset both FIFOs (default off)
+    ///
+    /// Bytes already in the FIFOs were framed at the old rate, so resetting them is
+    /// recommended whenever the baudrate changes mid-stream.
+    pub fn set_baud_change_fifo_reset(&mut self, enable: bool) {
+        self.baud_change_fifo_reset = enable;
+    }
+
+    /// Changes the baudrate of a running channel and then resets both FIFOs so no bytes
+    /// received or queued at the old rate are decoded at the new one
+    pub fn set_baudrate_with_fifo_reset(
+        &mut self,
+        channel: Channel,
+        baudrate: u32,
+    ) -> Result<(), Error<E>> {
+        self.set_baudrate(channel, baudrate)?;
+        if !self.baud_change_fifo_reset {
+            self.fifo_reset(channel, true, true)?;
+        }
         Ok(())
     }
 
@@ -280,34 +981,32 @@ where
         data_length: u8,
         parity_select: Parity,
         stop_length: u8,
-    ) -> Result<(), E> {
+    ) -> Result<(), Error<E>> {
         let mut temp_line_control_register: u8 = self.read_register(channel, 0x03)?;
         temp_line_control_register &= 0xC0;
-        {
-            println!("line_control_register Register: {temp_line_control_register:#04x}");
-        }
+        temp_line_control_register |= line_format(data_length, parity_select, stop_length);
+        self.write_register(channel, 0x03, temp_line_control_register)
+    }
 
-        match data_length {
-            5 => {}
-            6 => temp_line_control_register |= 0x01,
-            7 => temp_line_control_register |= 0x02,
-            _ => temp_line_control_register |= 0x03,
-        };
-        if stop_length == 2 {
-            temp_line_control_register |= 0x04;
-        }
-        match parity_select {
-            Parity::NoParity => {}
-            Parity::Odd => temp_line_control_register |= 0x08,
-            Parity::Even => temp_line_control_register |= 0x18,
-            Parity::ForcedParity1 => temp_line_control_register |= 0x28,
-            Parity::ForcedParity0 => temp_line_control_register |= 0x38,
+    /// Starts or ends a break, holding TX low for as long as it is enabled, LCR bit 6
+    ///
+    /// Only bit 6 is changed, the line format and the divisor latch bit are kept as read.
+    pub fn set_break(&mut self, channel: Channel, enable: bool) -> Result<(), Error<E>> {
+        let mut line_control_register = self.read_register(channel, 0x03)?;
+        if enable {
+            line_control_register |= 0x40;
+        } else {
+            line_control_register &= !0x40;
         }
-        self.write_register(channel, 0x03, temp_line_control_register)
+        self.write_register(channel, 0x03, line_control_register)
     }
 
     /// This register is used to set an I/O pin direction. Bit 0 to bit 7 controls GPIO0 to GPIO7.
-    pub fn gpio_set_pin_mode(&mut self, pin_number: GPIO, pin_direction: PinMode) -> Result<(), E> {
+    pub fn gpio_set_pin_mode(
+        &mut self,
+        pin_number: GPIO,
+        pin_direction: PinMode,
+    ) -> Result<(), Error<E>> {
         let mut temp_io_direction_register = self.read_register(Channel::A, 0xA)?;
         match pin_direction {
             PinMode::Output => temp_io_direction_register |= 0x01 << (pin_number as u8),
@@ -316,16 +1015,31 @@ where
         self.write_register(Channel::A, 0xA, temp_io_direction_register)
     }
 
-    pub fn gpio_set_pin_state(&mut self, pin_number: GPIO, pin_state: PinState) -> Result<(), E> {
-        let mut temp_io_direction_register = self.read_register(Channel::A, 0x0B)?;
+    /// Drives a GPIO output
+    ///
+    /// Reading IOState returns the pin levels while writing it sets the output latch, so the
+    /// new value is built from the levels of the pins IODir marks as outputs only. Input bits
+    /// are written as 0, which the chip ignores. Writing an input pin has no effect.
+    ///
+    /// GPIO7-4 are ignored while they are split off as modem pins, see
+    /// `set_gpio_channel_split`, only GPIO3-0 are general purpose then.
+    pub fn gpio_set_pin_state(
+        &mut self,
+        pin_number: GPIO,
+        pin_state: PinState,
+    ) -> Result<(), Error<E>> {
+        let io_direction_register = self.read_register(Channel::A, 0x0A)?;
+        let mut io_state_register = self.read_register(Channel::A, 0x0B)? & io_direction_register;
         match pin_state {
-            PinState::High => temp_io_direction_register |= 0x01 << (pin_number as u8),
-            PinState::Low => temp_io_direction_register &= !(0x01 << (pin_number as u8)),
+            PinState::High => io_state_register |= 0x01 << (pin_number as u8),
+            PinState::Low => io_state_register &= !(0x01 << (pin_number as u8)),
         }
-        self.write_register(Channel::A, 0x0B, temp_io_direction_register)
+        self.write_register(Channel::A, 0x0B, io_state_register)
     }
 
-    pub fn gpio_get_pin_state(&mut self, pin_number: GPIO) -> Result<PinState, E> {
+    /// Reads the live level of a GPIO, the external level for inputs and the driven one for
+    /// outputs
+    pub fn gpio_get_pin_state(&mut self, pin_number: GPIO) -> Result<PinState, Error<E>> {
         let temp_iostate = self.read_register(Channel::A, 0x0B)?;
 
         if (temp_iostate & (0x01 << (pin_number as u8))) == 0 {
@@ -334,29 +1048,106 @@ where
         Ok(PinState::High)
     }
 
-    pub fn gpio_get_port_state(&mut self) -> Result<u8, E> {
+    pub fn gpio_get_port_state(&mut self) -> Result<u8, Error<E>> {
         self.read_register(Channel::A, 0x0B)
     }
 
-    pub fn gpio_set_port_mode(&mut self, port_io: u8) -> Result<(), E> {
+    pub fn gpio_set_port_mode(&mut self, port_io: u8) -> Result<(), Error<E>> {
         self.write_register(Channel::A, 0x0A, port_io)
     }
 
-    pub fn gpio_set_port_state(&mut self, port_state: u8) -> Result<(), E> {
+    /// Sets the direction of all 8 GPIO pins at once, index 0 being GPIO0
+    pub fn gpio_set_directions(&mut self, directions: &[PinMode; 8]) -> Result<(), Error<E>> {
+        let io_direction_register =
+            directions
+                .iter()
+                .enumerate()
+                .fold(0u8, |reg, (pin, mode)| match mode {
+                    PinMode::Output => reg | 0x01 << pin,
+                    PinMode::Input => reg,
+                });
+        self.gpio_set_port_mode(io_direction_register)
+    }
+
+    /// Reads the direction of all 8 GPIO pins from IODir, index 0 being GPIO0
+    pub fn gpio_get_directions(&mut self) -> Result<[PinMode; 8], Error<E>> {
+        let io_direction_register = self.read_register(Channel::A, 0x0A)?;
+        let mut directions = [PinMode::Input; 8];
+        for (pin, mode) in directions.iter_mut().enumerate() {
+            if io_direction_register & (0x01 << pin) != 0 {
+                *mode = PinMode::Output;
+            }
+        }
+        Ok(directions)
+    }
+
+    /// Configures the pull resistor on a GPIO input
+    ///
+    /// None of the SC16IS740/750/752/760/762 have programmable pulls on their GPIO pins, so
+    /// only [`Pull::None`] is accepted and nothing is written. Up or down pulls return
+    /// [`Error::UnsupportedFeature`], fit external resistors on floating inputs instead.
+    pub fn gpio_set_pull(&mut self, _pin_number: GPIO, pull: Pull) -> Result<(), Error<E>> {
+        match pull {
+            Pull::None => Ok(()),
+            Pull::Up | Pull::Down => Err(Error::UnsupportedFeature),
+        }
+    }
+
+    pub fn gpio_set_port_state(&mut self, port_state: u8) -> Result<(), Error<E>> {
         self.write_register(Channel::A, 0x0B, port_state)
     }
 
-    pub fn set_pin_interrupt(&mut self, io_interrupt_enable_register: u8) -> Result<(), E> {
+    pub fn set_pin_interrupt(&mut self, io_interrupt_enable_register: u8) -> Result<(), Error<E>> {
+        self.write_register(Channel::A, 0x0C, io_interrupt_enable_register)
+    }
+
+    /// Enables or disables the input change interrupt of one GPIO in IOIntEna, keeping the
+    /// other pins' bits
+    ///
+    /// A change on an enabled input pin raises `INPUT_PIN_CHANGE_STATE` in `isr`, with
+    /// `gpio_latch` deciding whether the IOState change is held until read.
+    pub fn gpio_set_interrupt(&mut self, pin: GPIO, enable: bool) -> Result<(), Error<E>> {
+        let mut io_interrupt_enable_register = self.read_register(Channel::A, 0x0C)?;
+        if enable {
+            io_interrupt_enable_register |= 0x01 << (pin as u8);
+        } else {
+            io_interrupt_enable_register &= !(0x01 << (pin as u8));
+        }
         self.write_register(Channel::A, 0x0C, io_interrupt_enable_register)
     }
 
-    pub fn reset_device(&mut self) -> Result<(), E> {
+    /// Software resets the chip through IOControl bit 3 and waits for it to respond again
+    ///
+    /// Registers aren't reliable straight after the reset, so the scratchpad `ping` is retried
+    /// (up to 100 times, bus errors counting as not ready yet) before returning.
+    /// [`Error::Timeout`] if the chip never passes.
+    pub fn reset_device(&mut self) -> Result<(), Error<E>> {
         let mut reg: u8 = self.read_register(Channel::A, 0x0E)?;
         reg |= 0x08;
-        self.write_register(Channel::A, 0x0E, reg)
+        self.write_register(Channel::A, 0x0E, reg)?;
+        // Software reset puts FCR back to 0x00, FIFOs disabled
+        self.fcr = [0u8; 2];
+        self.tlr = [0u8; 2];
+        self.config = [None; 2];
+        self.prescaler = [Prescaler::DivideBy1; 2];
+        for _ in 0..RESET_PINGS {
+            if let Ok(true) = self.ping() {
+                return Ok(());
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Splits GPIO7-4 off as modem pins (IOControl bit 1), leaving GPIO3-0 as GPIO
+    ///
+    /// Per the datasheet the four pins become DSRA, DTRA, CDA and RIA. Writes to a split off
+    /// pin have no effect, check which pins are free with `gpio_availability`. Same as
+    /// `modem_pin`.
+    pub fn set_gpio_channel_split(&mut self, split: bool) -> Result<(), Error<E>> {
+        self.modem_pin(split)
     }
 
-    pub fn modem_pin(&mut self, state: bool) -> Result<(), E> {
+    pub fn modem_pin(&mut self, state: bool) -> Result<(), Error<E>> {
         let mut temp_io_control_register = self.read_register(Channel::A, 0x0E)?;
 
         if state {
@@ -368,7 +1159,23 @@ where
         self.write_register(Channel::A, 0x0E, temp_io_control_register)
     }
 
-    pub fn gpio_latch(&mut self, latch: bool) -> Result<(), E> {
+    /// Reports which GPIO pins are not taken over as modem pins
+    ///
+    /// IOControl bit 1 hands GPIO7-4 to channel A's DSR/DTR/CD/RI and, on dual channel
+    /// variants, bit 2 hands GPIO3-0 to channel B's.
+    pub fn gpio_availability(&mut self) -> Result<GpioAvailability, Error<E>> {
+        let io_control_register = self.read_register(Channel::A, 0x0E)?;
+        let mut available = 0xFF;
+        if io_control_register & 0x02 != 0 {
+            available &= 0x0F;
+        }
+        if self.variant.is_dual_channel() && io_control_register & 0x04 != 0 {
+            available &= 0xF0;
+        }
+        Ok(GpioAvailability { available })
+    }
+
+    pub fn gpio_latch(&mut self, latch: bool) -> Result<(), Error<E>> {
         let mut temp_io_control_register = self.read_register(Channel::A, 0x0E)?;
 
         if !latch {
@@ -380,208 +1187,3945 @@ where
         self.write_register(Channel::A, 0x0E, temp_io_control_register)
     }
 
-    pub fn interrupt_control(
-        &mut self,
-        channel: Channel,
+    /// Drives the RTS output from MCR bit 1, `true` asserts it (RTS pin low)
+    pub fn set_rts(&mut self, channel: Channel, active: bool) -> Result<(), Error<E>> {
+        self.set_modem_control_bits(channel, 0x02, active)
+    }
+
+    /// Drives the DTR output from MCR bit 0, `true` asserts it (DTR pin low)
+    ///
+    /// DTR shares a pin with a GPIO, enable the modem pins with `modem_pin` for channel A.
+    pub fn set_dtr(&mut self, channel: Channel, active: bool) -> Result<(), Error<E>> {
+        self.set_modem_control_bits(channel, 0x01, active)
+    }
+
+    /// Drives RTS and DTR together in a single MCR write, `true` asserts
+    ///
+    /// These are the only modem outputs, DSR, CD and RI are inputs read with `modem_status`.
+    /// The other MCR bits are preserved.
+    pub fn set_modem_outputs(
+        &mut self,
+        channel: Channel,
+        rts: bool,
+        dtr: bool,
+    ) -> Result<(), Error<E>> {
+        let modem_control_register = self.read_register(channel, 0x04)? & !0x03;
+        self.write_register(
+            channel,
+            0x04,
+            modem_control_register | (rts as u8) << 1 | dtr as u8,
+        )
+    }
+
+    /// Switches internal loopback, MCR bit 4, on or off
+    ///
+    /// In loopback TX is fed straight back into RX inside the chip and the TX pin idles high,
+    /// so the UART data path can be tested with nothing wired up.
+    pub fn set_loopback(&mut self, channel: Channel, enable: bool) -> Result<(), Error<E>> {
+        self.set_modem_control_bits(channel, 0x10, enable)
+    }
+
+    /// Whether internal loopback, MCR bit 4, is on
+    pub fn is_loopback_enabled(&mut self, channel: Channel) -> Result<bool, Error<E>> {
+        Ok(self.read_register(channel, 0x04)? & 0x10 != 0)
+    }
+
+    /// Sets or clears `mask` in MCR, leaving the other bits alone
+    fn set_modem_control_bits(
+        &mut self,
+        channel: Channel,
+        mask: u8,
+        set: bool,
+    ) -> Result<(), Error<E>> {
+        let mut modem_control_register = self.read_register(channel, 0x04)?;
+        if set {
+            modem_control_register |= mask;
+        } else {
+            modem_control_register &= !mask;
+        }
+        self.write_register(channel, 0x04, modem_control_register)
+    }
+
+    /// Reads and decodes MSR
+    ///
+    /// Reading MSR clears its delta bits, so `ring_detected` sees a fresh state after this.
+    pub fn modem_status(&mut self, channel: Channel) -> Result<ModemStatus, Error<E>> {
+        Ok(ModemStatus::from(self.read_register(channel, 0x06)?))
+    }
+
+    /// Sends `data` on an RS-485 transceiver whose driver enable is wired to a GPIO
+    ///
+    /// For boards without DE on RTS. `de_pin` is made an output and driven high before the
+    /// first byte, then low once the transmitter is empty (TEMT, LSR bit 6) rather than just
+    /// THR, as dropping DE while the shift register is still sending truncates the last byte.
+    ///
+    /// If sending fails or TEMT doesn't set within 100000 LSR polls, DE is still driven low
+    /// so the bus is released for the other nodes, and the original error is returned.
+    pub fn write_rs485(
+        &mut self,
+        channel: Channel,
+        de_pin: GPIO,
+        data: &[u8],
+    ) -> Result<(), Error<E>> {
+        self.gpio_set_pin_mode(de_pin, PinMode::Output)?;
+        let sent = self
+            .gpio_set_pin_state(de_pin, PinState::High)
+            .and_then(|_| self.write(channel, data))
+            .and_then(|_| self.wait_transmitter_empty(channel));
+        let released = self.gpio_set_pin_state(de_pin, PinState::Low);
+        sent.and(released)
+    }
+
+    /// Sends `data` on an RS-485 transceiver whose driver enable is wired to RTS, driving RTS
+    /// by hand instead of using the chip's auto direction control
+    ///
+    /// `de_active` is the RTS pin level that enables the driver. Auto direction control is
+    /// switched off, RTS is driven to `de_active` before the first byte and back once the
+    /// transmitter is empty (TEMT) so the last stop bit is not cut off. Prefer
+    /// `AutoRs485DirectionControl` where the board wiring allows it, it turns around faster.
+    ///
+    /// If sending fails or TEMT doesn't set within 100000 LSR polls, RTS is still put back to
+    /// release the bus and the original error is returned.
+    pub fn rs485_manual(
+        &mut self,
+        channel: Channel,
+        de_active: PinState,
+        data: &[u8],
+    ) -> Result<(), Error<E>> {
+        let extra_features_control_register = self.read_register(channel, 0x0F)?;
+        self.write_register(
+            channel,
+            0x0F,
+            extra_features_control_register & !(FeaturesRegister::AutoRs485DirectionControl as u8),
+        )?;
+        // MCR[1] set drives the RTS pin low
+        let sent = self
+            .set_rts(channel, de_active == PinState::Low)
+            .and_then(|_| self.write(channel, data))
+            .and_then(|_| self.wait_transmitter_empty(channel));
+        let released = self.set_rts(channel, de_active == PinState::High);
+        sent.and(released)
+    }
+
+    /// Reads the MSR trailing edge of RI (TERI) bit, set when the ring indicator input went
+    /// from active to inactive since MSR was last read
+    pub fn ring_detected(&mut self, channel: Channel) -> Result<bool, Error<E>> {
+        Ok(self.read_register(channel, 0x06)? & 0x04 != 0)
+    }
+
+    /// Reads the MSR carrier detect (CD) state
+    pub fn carrier_detected(&mut self, channel: Channel) -> Result<bool, Error<E>> {
+        Ok(self.read_register(channel, 0x06)? & 0x80 != 0)
+    }
+
+    pub fn interrupt_control(
+        &mut self,
+        channel: Channel,
         interrupt_enable_register: u8,
-    ) -> Result<(), E> {
+    ) -> Result<(), Error<E>> {
         self.write_register(channel, 0x01, interrupt_enable_register)
     }
 
-    pub fn interrupt_pending_test(&mut self, channel: Channel) -> Result<u8, E> {
+    /// Sets up a channel to interrupt once a burst of received data has stopped arriving
+    ///
+    /// The chip has no separate enable for the RX timeout interrupt (IIR 0x0C), it comes with
+    /// the RHR interrupt, IER bit 0. Raising the RX trigger level to 60 bytes (TLR) keeps the
+    /// threshold interrupt out of the way, so bursts shorter than that are reported only by the
+    /// timeout, four character times after the last byte. Other IER bits are left alone.
+    pub fn configure_rx_timeout_interrupt(&mut self, channel: Channel) -> Result<(), Error<E>> {
+        self.update_trigger_levels(channel, 0x0F, 0xF0)?;
+        let interrupt_enable_register = self.read_register(channel, 0x01)?;
+        self.write_register(channel, 0x01, interrupt_enable_register | 0x01)
+    }
+
+    /// Puts a channel into sleep mode with the RHR interrupt enabled so received data wakes it
+    ///
+    /// Sleep (IER bit 4) needs enhanced functions, which are switched on for the IER write and
+    /// EFR is restored afterwards. The chip only sleeps once both channels have sleep enabled,
+    /// no interrupt is pending and the RX line is idle. A falling edge on RX restarts the
+    /// crystal oscillator, which takes a few milliseconds, so the first characters after a
+    /// wake can be lost. Have the far end send a wake-up byte and wait before the real data.
+    pub fn set_sleep_with_wake(&mut self, channel: Channel) -> Result<(), Error<E>> {
+        self.with_enhanced_functions(channel, |device| {
+            let interrupt_enable_register = device.read_register(channel, 0x01)?;
+            device.write_register(channel, 0x01, interrupt_enable_register | 0x11)
+        })
+    }
+
+    /// Enters or leaves sleep mode, IER bit 4
+    ///
+    /// IER[4] is only writable with EFR[4] set, enhanced functions are switched on for the
+    /// write and EFR is restored afterwards. While asleep the oscillator stops, it restarts on
+    /// activity on RX or any register access over the host bus. Sleep is refused with
+    /// [`Error::TxNotEmpty`] while the TX FIFO or shift register still holds data, call
+    /// `flush_all` or wait for `transmitter_empty` first.
+    pub fn set_sleep(&mut self, channel: Channel, enable: bool) -> Result<(), Error<E>> {
+        if enable && !self.transmitter_empty(channel)? {
+            return Err(Error::TxNotEmpty);
+        }
+        self.with_enhanced_functions(channel, |device| {
+            let mut interrupt_enable_register = device.read_register(channel, 0x01)?;
+            if enable {
+                interrupt_enable_register |= 0x10;
+            } else {
+                interrupt_enable_register &= !0x10;
+            }
+            device.write_register(channel, 0x01, interrupt_enable_register)
+        })
+    }
+
+    pub fn interrupt_pending_test(&mut self, channel: Channel) -> Result<u8, Error<E>> {
         let ipt = self.read_register(channel, 0x02)?;
         Ok(ipt & 0x01)
     }
 
-    pub fn isr(&mut self, channel: Channel) -> Result<InterruptEventTest, E> {
+    /// Decodes the highest priority pending interrupt, `UNKNOWN` if none is pending
+    ///
+    /// Kept for compatibility, `next_interrupt` tells "nothing pending" apart.
+    pub fn isr(&mut self, channel: Channel) -> Result<InterruptEvent, Error<E>> {
+        Ok(self
+            .next_interrupt(channel)?
+            .unwrap_or(InterruptEvent::UNKNOWN))
+    }
+
+    /// Reads IIR and decodes the highest priority pending interrupt, `None` once IIR bit 0
+    /// reports nothing pending
+    ///
+    /// Servicing an event clears its source, so a shared IRQ line can be handled with
+    /// `while let Some(event) = device.next_interrupt(channel)? { ... }`.
+    pub fn next_interrupt(&mut self, channel: Channel) -> Result<Option<InterruptEvent>, Error<E>> {
         let mut interrupt_identification_register = self.read_register(channel, 0x02)?;
-        // interrupt_identification_register >>= 1;
-        interrupt_identification_register &= 0x3E;
-        match interrupt_identification_register {
-            0x06 => Ok(InterruptEventTest::RECEIVE_LINE_STATUS_ERROR),
-            0x0C => Ok(InterruptEventTest::RECEIVE_TIMEOUT_INTERRUPT),
-            0x04 => Ok(InterruptEventTest::RHR_INTERRUPT),
-            0x02 => Ok(InterruptEventTest::THR_INTERRUPT),
-            0x00 => Ok(InterruptEventTest::MODEM_INTERRUPT),
-            0x30 => Ok(InterruptEventTest::INPUT_PIN_CHANGE_STATE),
-            0x10 => Ok(InterruptEventTest::RECEIVE_XOFF),
-            0x20 => Ok(InterruptEventTest::CTS_RTS_CHANGE),
-            _ => Ok(InterruptEventTest::UNKNOWN),
+        if interrupt_identification_register & 0x01 != 0 {
+            return Ok(None);
         }
+        interrupt_identification_register &= 0x3E;
+        let event = match interrupt_identification_register {
+            0x06 => InterruptEvent::RECEIVE_LINE_STATUS_ERROR,
+            0x0C => InterruptEvent::RECEIVE_TIMEOUT_INTERRUPT,
+            0x04 => InterruptEvent::RHR_INTERRUPT,
+            0x02 => InterruptEvent::THR_INTERRUPT,
+            0x00 => InterruptEvent::MODEM_INTERRUPT,
+            0x30 => InterruptEvent::INPUT_PIN_CHANGE_STATE,
+            0x10 => InterruptEvent::RECEIVE_XOFF,
+            0x20 => InterruptEvent::CTS_RTS_CHANGE,
+            _ => InterruptEvent::UNKNOWN,
+        };
+        self.last_interrupt[channel as usize] = Some(event);
+        Ok(Some(event))
+    }
+
+    /// The interrupt cause decoded by the last `isr` or `next_interrupt` call on this channel
+    ///
+    /// Reading IIR can clear the THR empty source, so this lets several code paths look at
+    /// the cause after a single read.
+    pub fn last_interrupt(&self, channel: Channel) -> Option<InterruptEvent> {
+        self.last_interrupt[channel as usize]
     }
 
-    pub fn fifo_enable(&mut self, channel: Channel, state: bool) -> Result<(), E> {
-        let mut fifo_control_register = self.read_register(channel, 0x02)?;
+    /// Clears a pending special character detect condition.
+    ///
+    /// With special character detect enabled (EFR bit 5) every received byte is compared
+    /// against XOFF2. On a match the byte still goes into the RX FIFO, but IIR reports an
+    /// Xoff interrupt (0x10) which stays asserted until IIR is read, so the interrupt line
+    /// keeps re-firing unless the condition is cleared. This reads IIR to clear it and
+    /// returns true if the special character (Xoff) source was the one pending.
+    pub fn clear_special_char(&mut self, channel: Channel) -> Result<bool, Error<E>> {
+        let interrupt_identification_register = self.read_register(channel, 0x02)?;
+        Ok(interrupt_identification_register & 0x01 == 0
+            && interrupt_identification_register & 0x3E == 0x10)
+    }
 
+    /// Enables or disables the RX and TX FIFOs
+    ///
+    /// FCR is write-only (reading 0x02 returns IIR) so the driver keeps a shadow of it. The
+    /// other FCR bits are only latched once FCR[0] is set, so on every variant the enable bit
+    /// is written on its own first and the trigger bits from the shadow follow.
+    pub fn fifo_enable(&mut self, channel: Channel, state: bool) -> Result<(), Error<E>> {
         if !state {
-            fifo_control_register &= 0xFE;
+            self.fcr[channel as usize] &= 0xFE;
         } else {
-            fifo_control_register |= 0x01;
+            let (enable_first, fifo_control_register) =
+                fifo_enable_writes(self.fcr[channel as usize]);
+            if let Some(value) = enable_first {
+                self.write_register(channel, 0x02, value)?;
+            }
+            self.fcr[channel as usize] = fifo_control_register;
+        }
+        self.write_register(channel, 0x02, self.fcr[channel as usize])
+    }
+
+    /// Whether the FIFOs of `channel` were last enabled by `fifo_enable`
+    ///
+    /// FCR is write-only so this comes from the driver's shadow copy. With the FIFOs disabled
+    /// RHR is a single holding register and the read paths check LSR data ready instead of RXLVL.
+    pub fn fifo_enabled(&self, channel: Channel) -> bool {
+        self.fcr[channel as usize] & 0x01 != 0
+    }
+
+    /// Resets the RX and/or TX FIFO in a single FCR write
+    ///
+    /// The self-clearing reset bits (0x02 RX, 0x04 TX) are written along with the shadowed
+    /// FIFO enable and trigger bits, so the rest of FCR is unchanged. The resets only act while
+    /// the FIFOs are enabled. Resetting RX also drops a byte held by `peek`.
+    pub fn fifo_reset(
+        &mut self,
+        channel: Channel,
+        reset_rx: bool,
+        reset_tx: bool,
+    ) -> Result<(), Error<E>> {
+        let mut fifo_control_register = self.fcr[channel as usize];
+        if reset_rx {
+            fifo_control_register |= 0x02;
+            self.clear_peek(channel);
+        }
+        if reset_tx {
+            fifo_control_register |= 0x04;
+        }
+        if !reset_rx && !reset_tx {
+            return Ok(());
         }
         self.write_register(channel, 0x02, fifo_control_register)
     }
 
-    pub fn fifo_reset(&mut self, channel: Channel, state: bool) -> Result<(), E> {
-        let mut temp_fcr = self.read_register(channel, 0x02)?;
+    /// Checks LSR for an RX overrun and recovers from it
+    ///
+    /// If the overrun bit is set, received data was lost and what is left in the RX FIFO can't
+    /// be trusted, so the RX FIFO is reset and true is returned.
+    pub fn check_and_clear_overrun(&mut self, channel: Channel) -> Result<bool, Error<E>> {
+        if self.read_register(channel, 0x05)? & 0x02 == 0 {
+            return Ok(false);
+        }
+        self.fifo_reset(channel, true, false)?;
+        Ok(true)
+    }
 
-        if !state {
-            temp_fcr |= 0x04;
-        } else {
-            temp_fcr |= 0x02;
+    /// Resets the selected FIFO(s) and waits for the reset to complete
+    ///
+    /// The FCR reset bits are self-clearing but take a moment to act, so RXLVL/TXLVL are polled
+    /// (up to 10 times, `delay`ing 100 µs between polls) until the FIFO reads back empty.
+    /// Returns false if the reset could not be confirmed.
+    pub fn reset_fifo_and_wait<D: DelayUs>(
+        &mut self,
+        channel: Channel,
+        which: FifoSelect,
+        delay: &mut D,
+    ) -> Result<bool, Error<E>> {
+        self.fifo_reset(channel, which != FifoSelect::Tx, which != FifoSelect::Rx)?;
+        for _ in 0..FIFO_RESET_POLLS {
+            let rx_empty = which == FifoSelect::Tx || self.fifo_available_data(channel)? == 0;
+            let tx_empty = which == FifoSelect::Rx
+                || self.fifo_available_space(channel)? as usize == FIFO_DEPTH;
+            if rx_empty && tx_empty {
+                return Ok(true);
+            }
+            delay.delay_us(POLL_INTERVAL_US).ok();
         }
-        self.write_register(channel, 0x02, temp_fcr)
+        Ok(false)
     }
 
+    /// Sets the RX or TX FIFO trigger level in units of 4 bytes (1 to 15), keeping the other
+    /// direction's level
+    ///
+    /// Earlier versions wrote the TX level to TLR[7:4] and the RX level to TLR[3:0], the
+    /// reverse of the datasheet, and cleared the other nibble. The levels now go where
+    /// `set_trigger_levels` puts them, so callers relying on the old swap see their RX and
+    /// TX levels exchanged.
+    #[deprecated(
+        note = "use set_trigger_levels or set_tx_trigger; the RX/TX TLR nibbles are no longer swapped"
+    )]
     pub fn fifo_set_trigger_level(
         &mut self,
         channel: Channel,
         rx_fifo: bool,
         length: u8,
-    ) -> Result<(), E> {
-        let mut temp_reg = self.read_register(channel, 0x04)?;
-        temp_reg |= 0x04;
-        self.write_register(channel, 0x04, temp_reg)?; // SET MCR[2] to '1' to use TLR register or trigger level control in FCR
-                                                       // register
-
-        temp_reg = self.read_register(channel, 0x02)?;
-        self.write_register(channel, 0x02, temp_reg | 0x10)?; // set ERF[4] to '1' to use the  enhanced features
-
-        if !rx_fifo {
-            self.write_register(channel, 0x07, length << 4)?; // Tx FIFO trigger level setting
+    ) -> Result<(), Error<E>> {
+        if !(1..=15).contains(&length) {
+            return Err(Error::Config(ConfigError::InvalidTriggerLevel));
+        }
+        if rx_fifo {
+            self.update_trigger_levels(channel, 0x0F, length << 4)
         } else {
-            self.write_register(channel, 0x07, length)?; // Rx FIFO Trigger level setting
+            self.update_trigger_levels(channel, 0xF0, length)
         }
-        self.write_register(channel, 0x02, temp_reg) // restore EFR register
     }
 
-    pub fn fifo_available_data(&mut self, channel: Channel) -> Result<u8, E> {
-        // if self.fifo[channel as usize] == 0 {
-        self.fifo[channel as usize] = self.read_register(channel, 0x09)?;
-        // }
-        Ok(self.fifo[channel as usize])
+    /// Sets both FIFO trigger levels in TLR, in units of 4 bytes (1 to 15, i.e. 4 to 60 bytes)
+    ///
+    /// The RX level goes in TLR[7:4] and the TX level in TLR[3:0]. Enhanced functions and
+    /// MCR[2] are set to reach TLR and restored afterwards. The RX level is the fill at which
+    /// the RHR interrupt fires, the TX level the free space at which the THR interrupt fires.
+    pub fn set_trigger_levels(
+        &mut self,
+        channel: Channel,
+        rx_level: u8,
+        tx_level: u8,
+    ) -> Result<(), Error<E>> {
+        if !(1..=15).contains(&rx_level) || !(1..=15).contains(&tx_level) {
+            return Err(Error::Config(ConfigError::InvalidTriggerLevel));
+        }
+        self.update_trigger_levels(channel, 0x00, (rx_level << 4) | tx_level)
     }
 
-    pub fn fifo_available_space(&mut self, channel: Channel) -> Result<u8, E> {
-        self.read_register(channel, 0x08)
+    /// Sets the TX FIFO trigger level in units of 4 bytes (1 to 15, i.e. 4 to 60 bytes)
+    ///
+    /// Only the TX nibble of TLR is written, the RX trigger level is preserved. This is the
+    /// free space in the TX FIFO at which the THR interrupt fires for interrupt driven
+    /// transmit.
+    pub fn set_tx_trigger(&mut self, channel: Channel, level: u8) -> Result<(), Error<E>> {
+        if !(1..=15).contains(&level) {
+            return Err(Error::Config(ConfigError::InvalidTriggerLevel));
+        }
+        self.update_trigger_levels(channel, 0xF0, level)
     }
 
-    fn write_byte(&mut self, channel: Channel, val: &u8) -> Result<(), E> {
-        let mut tmp_line_status_register: u8 = 0;
-        while (tmp_line_status_register & 0x20) == 0 {
-            tmp_line_status_register = self.read_register(channel, 0x05)?;
-        }
-        self.write_register(channel, 0x00, *val)
+    /// Read-modify-writes TLR, keeping the `keep` bits and setting `bits`, and updates the
+    /// shadow checked by `verify_fifo_config`
+    fn update_trigger_levels(
+        &mut self,
+        channel: Channel,
+        keep: u8,
+        bits: u8,
+    ) -> Result<(), Error<E>> {
+        let trigger_level_register = self.with_tcr_tlr_access(channel, |device| {
+            let trigger_level_register = (device.read_register(channel, 0x07)? & keep) | bits;
+            device.write_register(channel, 0x07, trigger_level_register)?;
+            Ok(trigger_level_register)
+        })?;
+        self.tlr[channel as usize] = trigger_level_register;
+        Ok(())
     }
 
-    pub fn write(&mut self, channel: Channel, payload: &[u8]) -> Result<(), E> {
-        for byte in payload {
-            self.write_byte(channel, byte)?
+    /// Checks the FIFO enable state (IIR bits 7:6) and TLR read back as the driver left them
+    ///
+    /// FCR is write-only, so this catches a chip that silently missed a FIFO configuration
+    /// write. [`Error::Mismatch`] if either differs.
+    pub fn verify_fifo_config(&mut self, channel: Channel) -> Result<(), Error<E>> {
+        let fifos_enabled = self.read_register(channel, 0x02)? & 0xC0 == 0xC0;
+        let trigger_level_register =
+            self.with_tcr_tlr_access(channel, |device| device.read_register(channel, 0x07))?;
+        if fifos_enabled != self.fifo_enabled(channel)
+            || trigger_level_register != self.tlr[channel as usize]
+        {
+            return Err(Error::Mismatch);
         }
         Ok(())
     }
 
-    fn read_byte(&mut self, channel: Channel) -> Result<Option<u8>, E> {
-        if self.fifo_available_data(channel)? == 0 {
-            //println!("No data");
-            return Ok(None);
-        }
-        Ok(Some(self.read_register(channel, 0x00)?))
+    /// Selects the software flow control mode, EFR bits 3:0
+    ///
+    /// The rest of EFR, including the auto-RTS/CTS bits, is preserved. Set the characters
+    /// with `set_xon_xoff_chars` first.
+    pub fn set_software_flow_control(
+        &mut self,
+        channel: Channel,
+        mode: SwFlowMode,
+    ) -> Result<(), Error<E>> {
+        self.with_efr_access(channel, |device| {
+            let enhanced_features_register = device.read_register(channel, 0x02)?;
+            device.write_register(
+                channel,
+                0x02,
+                (enhanced_features_register & 0xF0) | mode.bits(),
+            )
+        })
+    }
+
+    /// Sets the XON and XOFF characters used by software flow control
+    ///
+    /// Both XON1/XON2 and XOFF1/XOFF2 are written, so the characters apply whichever of the
+    /// single character modes is selected. The two character `XonXoff1And2` mode then
+    /// matches the character twice over.
+    pub fn set_xon_xoff_chars(
+        &mut self,
+        channel: Channel,
+        xon: u8,
+        xoff: u8,
+    ) -> Result<(), Error<E>> {
+        self.with_efr_access(channel, |device| {
+            device.write_register(channel, 0x04, xon)?;
+            device.write_register(channel, 0x05, xon)?;
+            device.write_register(channel, 0x06, xoff)?;
+            device.write_register(channel, 0x07, xoff)
+        })
+    }
+
+    /// Reads back the software flow control mode from EFR along with the XON1 and XOFF1
+    /// characters
+    pub fn get_software_flow_control(
+        &mut self,
+        channel: Channel,
+    ) -> Result<(SwFlowMode, u8, u8), Error<E>> {
+        self.with_efr_access(channel, |device| {
+            let enhanced_features_register = device.read_register(channel, 0x02)?;
+            let xon1 = device.read_register(channel, 0x04)?;
+            let xoff1 = device.read_register(channel, 0x06)?;
+            Ok((
+                SwFlowMode::from_bits(enhanced_features_register),
+                xon1,
+                xoff1,
+            ))
+        })
     }
 
-    pub fn read(&mut self, channel: Channel, quantity: u8) -> Result<Vec<u8>, E> {
-        let mut buf_len: u8 = 0;
-        let mut buf: Vec<u8> = vec![];
-        if quantity > self.fifo_available_data(channel)? {
-            buf_len = self.fifo_available_data(channel)?;
+    /// Sets the hardware flow control RX FIFO thresholds, in bytes
+    ///
+    /// RTS is de-asserted to halt the sender once the RX FIFO holds `halt_bytes` and asserted
+    /// again when it drains to `resume_bytes`. Both must be multiples of 4 no larger than 60,
+    /// and as required by the datasheet the halt level must be above the resume level.
+    pub fn set_flow_thresholds_bytes(
+        &mut self,
+        channel: Channel,
+        halt_bytes: u8,
+        resume_bytes: u8,
+    ) -> Result<(), Error<E>> {
+        if halt_bytes > 60 || !halt_bytes.is_multiple_of(4) || !resume_bytes.is_multiple_of(4) {
+            return Err(Error::Config(ConfigError::InvalidFlowThreshold));
         }
-        for _ in 0..=buf_len {
-            if let Ok(Some(byte)) = self.read_byte(channel) {
-                buf.push(byte);
-            }
+        if halt_bytes <= resume_bytes {
+            return Err(Error::Config(ConfigError::InvalidFlowThreshold));
         }
-        Ok(buf)
+        let transmission_control_register = ((resume_bytes / 4) << 4) | (halt_bytes / 4);
+        self.with_tcr_tlr_access(channel, |device| {
+            device.write_register(channel, 0x06, transmission_control_register)
+        })
     }
 
-    pub fn read_all(&mut self, channel: Channel) -> Result<Vec<u8>, E> {
-        let mut buf: Vec<u8> = vec![];
-        for _ in 0..=self.fifo_available_data(channel)? {
-            if let Ok(Some(byte)) = self.read_byte(channel) {
-                buf.push(byte);
-            }
+    /// Sets the hardware flow control RX FIFO thresholds in TCR, in units of 4 bytes (0 to 15)
+    ///
+    /// Same as `set_flow_thresholds_bytes` with the levels given as TCR nibbles: `halt` goes in
+    /// TCR[3:0] and `resume` in TCR[7:4], as laid out in the datasheet.
+    pub fn set_flow_trigger_levels(
+        &mut self,
+        channel: Channel,
+        resume: u8,
+        halt: u8,
+    ) -> Result<(), Error<E>> {
+        if resume > 0x0F || halt > 0x0F {
+            return Err(Error::Config(ConfigError::InvalidFlowThreshold));
         }
-        Ok(buf)
+        self.set_flow_thresholds_bytes(channel, halt * 4, resume * 4)
     }
 
-    pub fn enable_features(
+    /// Enables or disables auto-RTS and auto-CTS hardware flow control, EFR bits 6 and 7
+    ///
+    /// Enhanced functions (EFR[4]) are switched on as well, the other EFR bits are preserved.
+    /// Auto-RTS follows the thresholds set with `set_flow_thresholds_bytes`.
+    pub fn set_hardware_flow_control(
         &mut self,
         channel: Channel,
-        feature: FeaturesRegister,
-        enable: bool,
-    ) -> Result<(), E> {
-        let mut temp_extra_features_control_register = self.read_register(channel, 0xF)?;
+        rts: bool,
+        cts: bool,
+    ) -> Result<(), Error<E>> {
+        self.with_efr_access(channel, |device| {
+            let mut enhanced_features_register = device.read_register(channel, 0x02)? | 0x10;
+            enhanced_features_register &= !0xC0;
+            if rts {
+                enhanced_features_register |= 0x40;
+            }
+            if cts {
+                enhanced_features_register |= 0x80;
+            }
+            device.write_register(channel, 0x02, enhanced_features_register)
+        })
+    }
 
-        if !enable {
-            temp_extra_features_control_register |= feature as u8;
-        } else {
-            temp_extra_features_control_register &= !(feature as u8);
-        }
-        self.write_register(channel, 0xF, temp_extra_features_control_register)
+    /// Reads back the hardware flow control thresholds as (halt, resume), in bytes
+    pub fn get_flow_thresholds(&mut self, channel: Channel) -> Result<(u8, u8), Error<E>> {
+        let transmission_control_register =
+            self.with_tcr_tlr_access(channel, |device| device.read_register(channel, 0x06))?;
+        Ok((
+            (transmission_control_register & 0x0F) * 4,
+            (transmission_control_register >> 4) * 4,
+        ))
     }
 
-    pub fn ping(&mut self) -> Result<bool, E> {
-        self.write_register(Channel::A, 0x07, 0x55)?;
+    /// How many more bytes can arrive before auto-RTS halts the sender
+    ///
+    /// Computed as the TCR halt level minus the current RXLVL, saturating at 0.
+    pub fn rx_headroom(&mut self, channel: Channel) -> Result<u8, Error<E>> {
+        let (halt_level, _) = self.get_flow_thresholds(channel)?;
+        Ok(halt_level.saturating_sub(self.fifo_available_data(channel)?))
+    }
 
-        if self.read_register(Channel::A, 0x07)? != 0x55 {
-            return Ok(false);
-        }
+    /// Runs `f` with the enhanced register set (EFR, XON1/2, XOFF1/2) selected by writing 0xBF
+    /// to LCR, then restores LCR
+    fn with_efr_access<R>(
+        &mut self,
+        channel: Channel,
+        f: impl FnOnce(&mut Self) -> Result<R, Error<E>>,
+    ) -> Result<R, Error<E>> {
+        let line_control_register = self.read_register(channel, 0x03)?;
+        self.write_register(channel, 0x03, 0xBF)?;
+        let result = f(self);
+        self.write_register(channel, 0x03, line_control_register)?;
+        result
+    }
 
-        self.write_register(Channel::A, 0x07, 0xAA)?;
+    /// Runs `f` with enhanced functions (EFR[4]) switched on, then restores EFR
+    ///
+    /// EFR[4] is needed to write IER[7:4], FCR[5:4] and MCR[7:5].
+    fn with_enhanced_functions<R>(
+        &mut self,
+        channel: Channel,
+        f: impl FnOnce(&mut Self) -> Result<R, Error<E>>,
+    ) -> Result<R, Error<E>> {
+        let enhanced_features_register = self.with_efr_access(channel, |device| {
+            let efr = device.read_register(channel, 0x02)?;
+            device.write_register(channel, 0x02, efr | 0x10)?;
+            Ok(efr)
+        })?;
 
-        if self.read_register(Channel::A, 0x07)? != 0xAA {
-            return Ok(false);
-        }
+        let result = f(self);
 
-        self.write_register(Channel::B, 0x07, 0x55)?;
+        self.with_efr_access(channel, |device| {
+            device.write_register(channel, 0x02, enhanced_features_register)
+        })?;
+        result
+    }
 
-        if self.read_register(Channel::B, 0x07)? != 0x55 {
-            return Ok(false);
-        }
+    /// Runs `f` with TCR/TLR mapped over 0x06/0x07, which needs enhanced functions (EFR[4]) and
+    /// MCR[2] set. EFR and MCR are restored afterwards.
+    fn with_tcr_tlr_access<R>(
+        &mut self,
+        channel: Channel,
+        f: impl FnOnce(&mut Self) -> Result<R, Error<E>>,
+    ) -> Result<R, Error<E>> {
+        self.with_enhanced_functions(channel, |device| {
+            let modem_control_register = device.read_register(channel, 0x04)?;
+            device.write_register(channel, 0x04, modem_control_register | 0x04)?;
 
-        self.write_register(Channel::B, 0x07, 0xAA)?;
+            let result = f(device);
 
-        if self.read_register(Channel::B, 0x07)? != 0xAA {
-            return Ok(false);
+            device.write_register(channel, 0x04, modem_control_register)?;
+            result
+        })
+    }
+
+    pub fn fifo_available_data(&mut self, channel: Channel) -> Result<u8, Error<E>> {
+        // if self.fifo[channel as usize] == 0 {
+        self.fifo[channel as usize] = self.read_register(channel, 0x09)?;
+        // }
+        Ok(self.fifo[channel as usize])
+    }
+
+    pub fn fifo_available_space(&mut self, channel: Channel) -> Result<u8, Error<E>> {
+        self.read_register(channel, 0x08)
+    }
+
+    fn write_byte(&mut self, channel: Channel, val: &u8) -> Result<(), Error<E>> {
+        self.flush_timeout(channel, THR_EMPTY_POLLS)?;
+        self.write_register(channel, 0x00, *val)?;
+        self.bytes_transmitted[channel as usize] += 1;
+        Ok(())
+    }
+
+    /// Estimates how long the bytes queued in the TX FIFO (64 - TXLVL) take to go out
+    ///
+    /// Uses the character time of the config passed to `initialise`, `None` if the channel
+    /// hasn't been initialised. The character still in the shift register isn't counted.
+    pub fn estimated_drain_time(&mut self, channel: Channel) -> Result<Option<Duration>, Error<E>> {
+        let Some(config) = self.config[channel as usize] else {
+            return Ok(None);
+        };
+        let queued = FIFO_DEPTH.saturating_sub(self.fifo_available_space(channel)? as usize);
+        Ok(Some(config.char_time(queued as u32)))
+    }
+
+    /// Number of bytes handed to the TX FIFO on this channel since the driver was created
+    ///
+    /// The chip has no transmit counter, this is tracked by the driver's write paths.
+    /// Subtracting the bytes still queued (64 - TXLVL) estimates what has gone out on the wire.
+    pub fn bytes_transmitted(&self, channel: Channel) -> u64 {
+        self.bytes_transmitted[channel as usize]
+    }
+
+    /// Writes `data` into THR using as few I2C transactions as the chunk size allows
+    ///
+    /// THR keeps its register address for the whole transaction so every byte lands in the TX
+    /// FIFO. No space check is made, the caller must know the FIFO can take `data`.
+    pub fn write_fifo_burst(&mut self, channel: Channel, data: &[u8]) -> Result<(), Error<E>> {
+        for chunk in data.chunks(self.max_i2c_chunk) {
+            self.transport.write_registers(channel, 0x00, chunk)?;
+            self.bytes_transmitted[channel as usize] += chunk.len() as u64;
+            #[cfg(feature = "trace")]
+            for value in chunk {
+                self.trace(TraceEvent::Write {
+                    channel,
+                    reg: 0x00,
+                    value: *value,
+                });
+            }
         }
+        Ok(())
+    }
 
-        Ok(true)
+    /// Fills `buf` from RHR using as few I2C transactions as the chunk size allows
+    ///
+    /// No level check is made, the caller must know the RX FIFO holds at least `buf.len()` bytes.
+    pub fn read_fifo_burst(&mut self, channel: Channel, buf: &mut [u8]) -> Result<(), Error<E>> {
+        for chunk in buf.chunks_mut(self.max_i2c_chunk) {
+            self.transport.read_registers(channel, 0x00, chunk)?;
+            #[cfg(feature = "trace")]
+            for value in chunk.iter() {
+                self.trace(TraceEvent::Read {
+                    channel,
+                    reg: 0x00,
+                    value: *value,
+                });
+            }
+        }
+        Ok(())
     }
 
-    pub fn flush(&mut self, channel: Channel) -> Result<(), E> {
-        let mut tmp_line_status_register: u8 = 0;
+    /// Sends all of `data`, bursting as much as the TX FIFO has room for each time
+    ///
+    /// When the FIFO is full TXLVL is re-polled every 100 µs, giving up with
+    /// [`Error::Timeout`] after 100 ms without progress. If auto-CTS flow control is enabled
+    /// the wait while CTS is de-asserted is not counted, the far end is holding us off on
+    /// purpose and the chip resumes once it asserts CTS again.
+    pub fn send<D: DelayUs>(
+        &mut self,
+        channel: Channel,
+        data: &[u8],
+        delay: &mut D,
+    ) -> Result<(), Error<E>> {
+        let auto_cts =
+            self.with_efr_access(channel, |device| device.read_register(channel, 0x02))? & 0x80
+                != 0;
+        let mut remaining = data;
+        let mut stalled_polls = 0;
+        while !remaining.is_empty() {
+            let space = self.fifo_available_space(channel)? as usize;
+            if space == 0 {
+                if !auto_cts || self.read_register(channel, 0x06)? & 0x10 != 0 {
+                    stalled_polls += 1;
+                    if stalled_polls > TX_STALL_POLLS {
+                        return Err(Error::Timeout);
+                    }
+                }
+                delay.delay_us(POLL_INTERVAL_US).ok();
+                continue;
+            }
+            let (burst, rest) = remaining.split_at(space.min(remaining.len()));
+            self.write_fifo_burst(channel, burst)?;
+            remaining = rest;
+            stalled_polls = 0;
+        }
+        Ok(())
+    }
 
-        while (tmp_line_status_register & 0x20) == 0 {
-            tmp_line_status_register = self.read_register(channel, 0x05)?;
+    pub fn write(&mut self, channel: Channel, payload: &[u8]) -> Result<(), Error<E>> {
+        for byte in payload {
+            self.write_byte(channel, byte)?
         }
         Ok(())
     }
 
-    pub fn peek(&mut self, channel: Channel) -> Result<(), E> {
-        if self.peek_flags[channel as usize] {
-            self.peek_buf[channel as usize] = self.read_byte(channel)?;
+    /// Sends `data` one byte at a time with an idle gap on the line between bytes
+    ///
+    /// Each byte is left to finish transmitting (TEMT) before `delay` waits out `gap`, for
+    /// peripherals that can't take back to back characters. No gap follows the last byte.
+    /// [`Error::Timeout`] if a byte doesn't finish within 100000 LSR polls.
+    pub fn write_with_gap<D: DelayUs>(
+        &mut self,
+        channel: Channel,
+        data: &[u8],
+        gap: Duration,
+        delay: &mut D,
+    ) -> Result<(), Error<E>> {
+        let gap_us = u32::try_from(gap.as_micros()).unwrap_or(u32::MAX);
+        for (index, byte) in data.iter().enumerate() {
+            if index > 0 {
+                delay.delay_us(gap_us).ok();
+            }
+            self.write_byte(channel, byte)?;
+            self.wait_transmitter_empty(channel)?;
+        }
+        Ok(())
+    }
 
-            if self.peek_buf[channel as usize].is_some() {
-                self.peek_flags[channel as usize] = true;
+    /// Waits until the TX FIFO has room for at least `n` bytes
+    ///
+    /// TXLVL is polled every 100 µs using `delay`, giving up with [`Error::Timeout`] once
+    /// `timeout` has passed. More than 64 bytes never fit and always time out.
+    pub fn wait_tx_space<D: DelayUs>(
+        &mut self,
+        channel: Channel,
+        n: usize,
+        timeout: Duration,
+        delay: &mut D,
+    ) -> Result<(), Error<E>> {
+        let mut waited_us: u128 = 0;
+        while (self.fifo_available_space(channel)? as usize) < n {
+            if waited_us >= timeout.as_micros() {
+                return Err(Error::Timeout);
             }
+            delay.delay_us(POLL_INTERVAL_US).ok();
+            waited_us += POLL_INTERVAL_US as u128;
         }
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn it_works() {
-        let result = 2 + 2;
-        assert_eq!(result, 4);
+    /// Writes as much of `payload` as the TX FIFO has room for right now, never busy-waiting
+    ///
+    /// TXLVL is read once and returns the number of bytes accepted, `Ok(0)` when the FIFO is full.
+    pub fn write_nonblocking(
+        &mut self,
+        channel: Channel,
+        payload: &[u8],
+    ) -> Result<usize, Error<E>> {
+        self.write_bulk(channel, payload)
+    }
+
+    /// Writes as much of `data` as fits in the TX FIFO in a single block write to THR
+    ///
+    /// TXLVL is read once and only what fits is written (split only if `set_max_i2c_chunk` is
+    /// smaller), the partial count is returned and the caller sends the rest later. Use `send`
+    /// to wait for space and push a whole payload.
+    pub fn write_bulk(&mut self, channel: Channel, data: &[u8]) -> Result<usize, Error<E>> {
+        let space = (self.fifo_available_space(channel)? as usize).min(data.len());
+        self.write_fifo_burst(channel, &data[..space])?;
+        Ok(space)
+    }
+
+    fn read_byte(&mut self, channel: Channel) -> Result<Option<u8>, Error<E>> {
+        if self.peek_flags[channel as usize] {
+            self.peek_flags[channel as usize] = false;
+            return Ok(self.peek_buf[channel as usize].take());
+        }
+        if self.rx_available(channel)? == 0 {
+            //println!("No data");
+            return Ok(None);
+        }
+        Ok(Some(self.read_register(channel, 0x00)?))
+    }
+
+    /// `read_byte` for callers that already know a byte is waiting, skips the level check
+    fn read_byte_unchecked(&mut self, channel: Channel) -> Result<u8, Error<E>> {
+        if self.peek_flags[channel as usize] {
+            self.peek_flags[channel as usize] = false;
+            return Ok(self.peek_buf[channel as usize].take().unwrap_or_default());
+        }
+        self.read_register(channel, 0x00)
+    }
+
+    /// Fills as much of `buf` as the RX FIFO holds, returning the number of bytes read
+    ///
+    /// RXLVL is read once and the data comes out of RHR in a single block read (split only if
+    /// `set_max_i2c_chunk` is smaller), instead of a level check and transaction per byte.
+    /// This relies on the FIFOs having been enabled through this driver, `initialise` or
+    /// `fifo_enable`, otherwise at most one byte is read per call.
+    pub fn read_bulk(&mut self, channel: Channel, buf: &mut [u8]) -> Result<usize, Error<E>> {
+        let count = (self.available_with_peek(channel)? as usize).min(buf.len());
+        let mut start = 0;
+        if count > 0 && self.peek_flags[channel as usize] {
+            buf[0] = self.read_byte_unchecked(channel)?;
+            start = 1;
+        }
+        self.read_fifo_burst(channel, &mut buf[start..count])?;
+        Ok(count)
+    }
+
+    /// Drains whatever has been received into `scratch` and returns the filled part
+    ///
+    /// `read_bulk` into a FIFO sized buffer owned by the caller, so a receive loop reuses the
+    /// same buffer and never allocates. The slice is empty when nothing is waiting.
+    pub fn poll_into<'b>(
+        &mut self,
+        channel: Channel,
+        scratch: &'b mut [u8; FIFO_DEPTH],
+    ) -> Result<&'b [u8], Error<E>> {
+        let count = self.read_bulk(channel, scratch)?;
+        Ok(&scratch[..count])
+    }
+
+    /// Reads the next byte along with any receive error reported against it
+    ///
+    /// LSR is read first, its parity, framing and break flags belong to the character at the
+    /// top of the RX FIFO, then that character is read from RHR. A break also shows as a
+    /// framing error and is reported as [`LineError::Break`]. A byte already held by `peek`
+    /// is returned as is, its flags were consumed when it was peeked.
+    pub fn read_byte_checked(
+        &mut self,
+        channel: Channel,
+    ) -> Result<Result<u8, LineError>, Error<E>> {
+        if self.peek_flags[channel as usize] {
+            return Ok(self.read_byte(channel)?.ok_or(LineError::Empty));
+        }
+        let line_status = self.line_status(channel)?;
+        if !line_status.data_ready {
+            return Ok(Err(LineError::Empty));
+        }
+        let byte = self.read_register(channel, 0x00)?;
+        Ok(if line_status.break_interrupt {
+            Err(LineError::Break)
+        } else if line_status.framing_error {
+            Err(LineError::Framing(byte))
+        } else if line_status.parity_error {
+            Err(LineError::Parity(byte))
+        } else {
+            Ok(byte)
+        })
+    }
+
+    /// Reads up to `quantity` bytes, as many as are available
+    ///
+    /// The RX level is read once and exactly that many bytes are taken, bytes arriving in the
+    /// meantime are left for the next call.
+    pub fn read(&mut self, channel: Channel, quantity: u8) -> Result<Vec<u8>, Error<E>> {
+        let buf_len = quantity.min(self.available_with_peek(channel)?);
+        let mut buf: Vec<u8> = Vec::with_capacity(buf_len as usize);
+        for _ in 0..buf_len {
+            buf.push(self.read_byte_unchecked(channel)?);
+        }
+        Ok(buf)
+    }
+
+    /// RXLVL plus the byte held by `peek`, if any
+    fn available_with_peek(&mut self, channel: Channel) -> Result<u8, Error<E>> {
+        Ok(self.rx_available(channel)? + self.peek_flags[channel as usize] as u8)
+    }
+
+    /// Bytes waiting in RHR, RXLVL in FIFO mode or LSR data ready (0 or 1) otherwise
+    fn rx_available(&mut self, channel: Channel) -> Result<u8, Error<E>> {
+        if self.fifo_enabled(channel) {
+            self.fifo_available_data(channel)
+        } else {
+            Ok(self.read_register(channel, 0x05)? & 0x01)
+        }
+    }
+
+    /// Reads until `max` bytes have been collected or no more data has arrived within `timeout`
+    ///
+    /// Whatever was gathered is returned, which suits variable length responses with a known
+    /// upper bound. RXLVL is polled every 100 µs using `delay` while the FIFO is empty.
+    pub fn read_up_to<D: DelayUs>(
+        &mut self,
+        channel: Channel,
+        max: usize,
+        timeout: Duration,
+        delay: &mut D,
+    ) -> Result<Vec<u8>, Error<E>> {
+        let mut buf: Vec<u8> = Vec::with_capacity(max);
+        if max > 0 && self.peek_flags[channel as usize] {
+            buf.extend(self.read_byte(channel)?);
+        }
+        let mut waited_us: u128 = 0;
+        while buf.len() < max {
+            let available = self.rx_available(channel)? as usize;
+            if available == 0 {
+                if waited_us >= timeout.as_micros() {
+                    break;
+                }
+                delay.delay_us(POLL_INTERVAL_US).ok();
+                waited_us += POLL_INTERVAL_US as u128;
+                continue;
+            }
+            for _ in 0..available.min(max - buf.len()) {
+                buf.push(self.read_register(channel, 0x00)?);
+            }
+        }
+        Ok(buf)
+    }
+
+    /// Iterates over the bytes currently in the RX FIFO, including one held by `peek`
+    ///
+    /// The RX level is read once, here, and exactly that many bytes are yielded, so bytes
+    /// arriving later are left for the next call. A bus error, including one reading the
+    /// level, is yielded as an `Err` item and ends the iteration.
+    pub fn rx_iter<'a>(
+        &'a mut self,
+        channel: Channel,
+    ) -> impl Iterator<Item = Result<u8, Error<E>>> + 'a
+    where
+        E: 'a,
+    {
+        let (mut remaining, mut pending_error) = match self.available_with_peek(channel) {
+            Ok(count) => (count, None),
+            Err(error) => (0, Some(error)),
+        };
+        core::iter::from_fn(move || {
+            if let Some(error) = pending_error.take() {
+                return Some(Err(error));
+            }
+            if remaining == 0 {
+                return None;
+            }
+            let byte = self.read_byte_unchecked(channel);
+            remaining = if byte.is_ok() { remaining - 1 } else { 0 };
+            Some(byte)
+        })
+    }
+
+    /// Reads everything currently in the RX FIFO, going by a single read of the RX level
+    pub fn read_all(&mut self, channel: Channel) -> Result<Vec<u8>, Error<E>> {
+        let available = self.available_with_peek(channel)?;
+        let mut buf: Vec<u8> = Vec::with_capacity(available as usize);
+        for _ in 0..available {
+            buf.push(self.read_byte_unchecked(channel)?);
+        }
+        Ok(buf)
+    }
+
+    pub fn enable_features(
+        &mut self,
+        channel: Channel,
+        feature: FeaturesRegister,
+        enable: bool,
+    ) -> Result<(), Error<E>> {
+        let mut temp_extra_features_control_register = self.read_register(channel, 0xF)?;
+
+        if enable {
+            temp_extra_features_control_register |= feature as u8;
+        } else {
+            temp_extra_features_control_register &= !(feature as u8);
+        }
+        self.write_register(channel, 0xF, temp_extra_features_control_register)
+    }
+
+    /// Reads back the Extra Features Control Register
+    pub fn get_features(&mut self, channel: Channel) -> Result<FeaturesState, Error<E>> {
+        Ok(FeaturesState::from(self.read_register(channel, 0xF)?))
+    }
+
+    /// Writes 0x55 and 0xAA to SPR on every channel the configured variant has and checks
+    /// they read back
+    ///
+    /// SPR is left holding 0xAA. Register 0x07 is only SPR in the general register set, with
+    /// MCR[2] and EFR[4] set it is TLR instead and gets overwritten. Use `is_present` for a
+    /// check that leaves the chip as it was.
+    pub fn ping(&mut self) -> Result<bool, Error<E>> {
+        for &channel in self.channels() {
+            for pattern in [0x55, 0xAA] {
+                self.write_register(channel, 0x07, pattern)?;
+
+                if self.read_register(channel, 0x07)? != pattern {
+                    return Ok(false);
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Checks the chip answers by writing the complement of SPR and reading it back,
+    /// restoring the original value afterwards
+    ///
+    /// If LCR is 0xBF, selecting the enhanced register set that maps XOFF2 over 0x07, LCR[7]
+    /// is cleared for the check. Likewise if MCR[2] has TLR mapped over 0x07 it is cleared
+    /// and set again. Both are put back, so TLR, XOFF2 and the rest of the configuration are
+    /// untouched. Only channel A is used.
+    pub fn is_present(&mut self) -> Result<bool, Error<E>> {
+        let line_control_register = self.read_register(Channel::A, 0x03)?;
+        if line_control_register == 0xBF {
+            self.write_register(Channel::A, 0x03, line_control_register & 0x7F)?;
+        }
+        let modem_control_register = self.read_register(Channel::A, 0x04)?;
+        if modem_control_register & 0x04 != 0 {
+            self.write_register(Channel::A, 0x04, modem_control_register & !0x04)?;
+        }
+        let scratchpad_register = self.read_register(Channel::A, 0x07)?;
+        self.write_register(Channel::A, 0x07, !scratchpad_register)?;
+        let present = self.read_register(Channel::A, 0x07)? == !scratchpad_register;
+        self.write_register(Channel::A, 0x07, scratchpad_register)?;
+        if modem_control_register & 0x04 != 0 {
+            self.write_register(Channel::A, 0x04, modem_control_register)?;
+        }
+        if line_control_register == 0xBF {
+            self.write_register(Channel::A, 0x03, line_control_register)?;
+        }
+        Ok(present)
+    }
+
+    /// Probes channel B's scratchpad to tell single from dual UART parts, and remembers the
+    /// result as the configured variant
+    ///
+    /// Different values are written to SPR of both channels: on a single UART part channel B
+    /// either doesn't hold the value or aliases channel A. The IrDA fast mode parts are
+    /// register compatible with the others, so a variant set to SC16IS760/762 stays in that
+    /// family and otherwise SC16IS750/752 is reported. Both SPR values are put back.
+    pub fn detect_variant(&mut self) -> Result<Variant, Error<E>> {
+        let scratchpad_a = self.read_register(Channel::A, 0x07)?;
+        let scratchpad_b = self.read_register(Channel::B, 0x07)?;
+        self.write_register(Channel::A, 0x07, 0x5A)?;
+        self.write_register(Channel::B, 0x07, 0xA5)?;
+        let dual_channel = self.read_register(Channel::B, 0x07)? == 0xA5
+            && self.read_register(Channel::A, 0x07)? == 0x5A;
+        if dual_channel {
+            self.write_register(Channel::B, 0x07, scratchpad_b)?;
+        }
+        self.write_register(Channel::A, 0x07, scratchpad_a)?;
+
+        let irda_fast = matches!(self.variant, Variant::SC16IS760 | Variant::SC16IS762);
+        self.variant = match (dual_channel, irda_fast) {
+            (false, false) => Variant::SC16IS750,
+            (true, false) => Variant::SC16IS752,
+            (false, true) => Variant::SC16IS760,
+            (true, true) => Variant::SC16IS762,
+        };
+        Ok(self.variant)
+    }
+
+    /// Scratchpad test with a caller supplied list of patterns, e.g. walking ones and zeros
+    ///
+    /// Each pattern is written to and read back from SPR on every channel the configured
+    /// variant has, stopping at the first mismatch. The original SPR value is put back.
+    pub fn ping_with_patterns(&mut self, patterns: &[u8]) -> Result<bool, Error<E>> {
+        for channel in self.channels() {
+            let scratchpad_register = self.read_register(*channel, 0x07)?;
+            let mut matched = true;
+            for pattern in patterns {
+                self.write_register(*channel, 0x07, *pattern)?;
+                if self.read_register(*channel, 0x07)? != *pattern {
+                    matched = false;
+                    break;
+                }
+            }
+            self.write_register(*channel, 0x07, scratchpad_register)?;
+            if !matched {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// The UART channels of the configured variant
+    fn channels(&self) -> &'static [Channel] {
+        if self.variant.is_dual_channel() {
+            &[Channel::A, Channel::B]
+        } else {
+            &[Channel::A]
+        }
+    }
+
+    /// Retries the scratchpad `ping` with exponential backoff until the device responds
+    ///
+    /// Makes one attempt plus up to `retries` more, waiting 1 ms before the first retry and
+    /// doubling each time. If every attempt fails with a bus error or a bad readback
+    /// [`Error::BusWedged`] is returned so the caller can power-cycle the bus.
+    pub fn recover_bus<D: DelayUs>(&mut self, retries: u8, delay: &mut D) -> Result<(), Error<E>> {
+        let mut backoff_us = RECOVER_BACKOFF_US;
+        for attempt in 0..=retries {
+            if attempt > 0 {
+                delay.delay_us(backoff_us).ok();
+                backoff_us = backoff_us.saturating_mul(2);
+            }
+            if let Ok(true) = self.ping() {
+                return Ok(());
+            }
+        }
+        Err(Error::BusWedged)
+    }
+
+    /// Measures round trip throughput by pushing `byte_count` bytes through internal loopback
+    ///
+    /// A counting pattern is written while the looped back data is drained, keeping no more
+    /// than 64 bytes in flight so the RX FIFO can't overrun. `clock` returns the time elapsed
+    /// since any fixed point, e.g. `|| start.elapsed()`. Gives up once nothing arrives for
+    /// 100 ms, polling every 100 µs using `delay`. MCR is restored afterwards.
+    pub fn benchmark_channel<D: DelayUs>(
+        &mut self,
+        channel: Channel,
+        byte_count: usize,
+        delay: &mut D,
+        mut clock: impl FnMut() -> Duration,
+    ) -> Result<ThroughputReport, Error<E>> {
+        let modem_control_register = self.read_register(channel, 0x04)?;
+        self.write_register(channel, 0x04, modem_control_register | 0x10)?;
+        self.fifo_reset(channel, true, false)?;
+
+        let pattern = |index: usize| index as u8;
+        let mut buf = [0u8; FIFO_DEPTH];
+        let (mut sent, mut received, mut errors) = (0, 0, 0);
+        let mut idle_us: u128 = 0;
+        let start = clock();
+        while received < byte_count && idle_us < LOOPBACK_TIMEOUT.as_micros() {
+            let room = (FIFO_DEPTH - (sent - received)).min(byte_count - sent);
+            if room > 0 {
+                let mut chunk = [0u8; FIFO_DEPTH];
+                for (offset, byte) in chunk[..room].iter_mut().enumerate() {
+                    *byte = pattern(sent + offset);
+                }
+                sent += self.write_bulk(channel, &chunk[..room])?;
+            }
+            let count = self.read_bulk(channel, &mut buf)?;
+            if count == 0 {
+                delay.delay_us(POLL_INTERVAL_US).ok();
+                idle_us += POLL_INTERVAL_US as u128;
+                continue;
+            }
+            idle_us = 0;
+            for (offset, byte) in buf[..count].iter().enumerate() {
+                if *byte != pattern(received + offset) {
+                    errors += 1;
+                }
+            }
+            received += count;
+        }
+        let elapsed = clock().saturating_sub(start);
+        self.write_register(channel, 0x04, modem_control_register)?;
+
+        let bytes_per_sec = match elapsed.as_micros() {
+            0 => 0,
+            micros => (received as u128 * 1_000_000 / micros) as u32,
+        };
+        Ok(ThroughputReport {
+            bytes_sent: sent,
+            bytes_received: received,
+            errors: errors + byte_count.saturating_sub(received),
+            elapsed,
+            bytes_per_sec,
+        })
+    }
+
+    /// Sends `pattern` through the UART in internal loopback and checks it comes back bit-exact
+    ///
+    /// The RX FIFO is reset first and MCR is restored afterwards, so the pattern must fit in
+    /// the 64 byte RX FIFO. Useful as a manufacturing test of the whole UART data path.
+    pub fn loopback_pattern_test<D: DelayUs>(
+        &mut self,
+        channel: Channel,
+        pattern: &[u8],
+        delay: &mut D,
+    ) -> Result<bool, Error<E>> {
+        let modem_control_register = self.read_register(channel, 0x04)?;
+        self.write_register(channel, 0x04, modem_control_register | 0x10)?;
+        self.fifo_reset(channel, true, false)?;
+
+        self.write(channel, pattern)?;
+        let received = self.read_up_to(channel, pattern.len(), LOOPBACK_TIMEOUT, delay)?;
+
+        self.write_register(channel, 0x04, modem_control_register)?;
+        Ok(received == pattern)
+    }
+
+    /// Snapshots registers 0x00 to 0x0F of the general register set, for comparing against
+    /// the datasheet while debugging
+    ///
+    /// RHR is not read as that would consume a received byte, index 0 is left 0. If LCR has
+    /// the divisor latch or enhanced register set selected, LCR[7] is cleared for the dump so
+    /// IER and IIR are read rather than DLL/DLH or EFR, and LCR is put back afterwards. Note
+    /// the reads still have their usual side effects: IIR clears a pending THR interrupt, LSR
+    /// the overrun flag and MSR its delta bits. With EFR[4] and MCR[2] set 0x06 and 0x07 read
+    /// back TCR and TLR. Wrap the result in [`RegisterDump`] for a labelled debug print.
+    pub fn dump_registers(&mut self, channel: Channel) -> Result<[u8; 16], Error<E>> {
+        let line_control_register = self.read_register(channel, 0x03)?;
+        if line_control_register & 0x80 != 0 {
+            self.write_register(channel, 0x03, line_control_register & 0x7F)?;
+        }
+        let mut registers = [0u8; 16];
+        let mut result = Ok(());
+        for (reg, value) in registers.iter_mut().enumerate().skip(1) {
+            match self.read_register(channel, reg as u8) {
+                Ok(register) => *value = register,
+                Err(error) => {
+                    result = Err(error);
+                    break;
+                }
+            }
+        }
+        if line_control_register & 0x80 != 0 {
+            self.write_register(channel, 0x03, line_control_register)?;
+            registers[0x03] = line_control_register;
+        }
+        result.map(|_| registers)
+    }
+
+    /// Reads and decodes LSR, including the RX error flags
+    ///
+    /// Reading LSR clears the overrun flag, and the parity, framing and break flags refer to
+    /// the character at the top of the RX FIFO, so check this before reading that character.
+    pub fn line_status(&mut self, channel: Channel) -> Result<LineStatus, Error<E>> {
+        Ok(LineStatus::from(self.read_register(channel, 0x05)?))
+    }
+
+    /// Whether THR (the TX FIFO in FIFO mode) is empty and can take more data, LSR bit 5
+    pub fn tx_ready(&mut self, channel: Channel) -> Result<bool, Error<E>> {
+        Ok(self.read_register(channel, 0x05)? & 0x20 != 0)
+    }
+
+    /// Whether both THR and the transmit shift register are empty, LSR bit 6 (TEMT)
+    ///
+    /// Unlike `tx_ready` this only becomes true once the last stop bit has left the TX pin,
+    /// which is what RS-485 direction turnaround has to wait for.
+    pub fn transmitter_empty(&mut self, channel: Channel) -> Result<bool, Error<E>> {
+        Ok(self.read_register(channel, 0x05)? & 0x40 != 0)
+    }
+
+    /// Waits for THR (the TX FIFO in FIFO mode) to empty, see `flush_timeout`
+    ///
+    /// Gives up with [`Error::Timeout`] after 100000 LSR polls, enough for a full FIFO at 300
+    /// baud on a 100 kHz I2C bus.
+    pub fn flush(&mut self, channel: Channel) -> Result<(), Error<E>> {
+        self.flush_timeout(channel, THR_EMPTY_POLLS)
+    }
+
+    /// Polls LSR up to `max_polls` times for THR to empty, returning [`Error::Timeout`] if it
+    /// never does
+    ///
+    /// Counting polls rather than time keeps this clock free, so a stuck THR or a wiring fault
+    /// can't hang the caller.
+    pub fn flush_timeout(&mut self, channel: Channel, max_polls: u32) -> Result<(), Error<E>> {
+        for _ in 0..max_polls {
+            if self.read_register(channel, 0x05)? & 0x20 != 0 {
+                return Ok(());
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Polls LSR up to 100000 times for the transmitter to empty (TEMT, LSR bit 6), returning
+    /// [`Error::Timeout`] if it never does
+    fn wait_transmitter_empty(&mut self, channel: Channel) -> Result<(), Error<E>> {
+        for _ in 0..THR_EMPTY_POLLS {
+            if self.transmitter_empty(channel)? {
+                return Ok(());
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Waits for every channel of the variant to finish transmitting (LSR TEMT), sharing one
+    /// `timeout` between them
+    ///
+    /// The channels still sending are polled every 100 µs using `delay`, giving up with
+    /// [`Error::Timeout`] once `timeout` has passed. Meant for a clean shutdown of both UARTs.
+    pub fn flush_all<D: DelayUs>(
+        &mut self,
+        timeout: Duration,
+        delay: &mut D,
+    ) -> Result<(), Error<E>> {
+        let mut drained = [false; 2];
+        let mut waited_us: u128 = 0;
+        loop {
+            for &channel in self.channels() {
+                if !drained[channel as usize] {
+                    drained[channel as usize] = self.transmitter_empty(channel)?;
+                }
+            }
+            if self
+                .channels()
+                .iter()
+                .all(|&channel| drained[channel as usize])
+            {
+                return Ok(());
+            }
+            if waited_us >= timeout.as_micros() {
+                return Err(Error::Timeout);
+            }
+            delay.delay_us(POLL_INTERVAL_US).ok();
+            waited_us += POLL_INTERVAL_US as u128;
+        }
+    }
+
+    /// Returns the next received byte without consuming it
+    ///
+    /// The byte is read out of RHR and held by the driver, so peeking again returns the same
+    /// byte and the next read returns it before touching the FIFO. `None` if nothing has
+    /// been received.
+    pub fn peek(&mut self, channel: Channel) -> Result<Option<u8>, Error<E>> {
+        if !self.peek_flags[channel as usize] {
+            self.peek_buf[channel as usize] = self.read_byte(channel)?;
+
+            if self.peek_buf[channel as usize].is_some() {
+                self.peek_flags[channel as usize] = true;
+            }
+        }
+        Ok(self.peek_buf[channel as usize])
+    }
+
+    /// Discards any byte buffered by `peek` so the next read goes to the hardware
+    ///
+    /// Called by `fifo_reset` on the RX FIFO, use it directly after reconfiguring a channel.
+    pub fn clear_peek(&mut self, channel: Channel) {
+        self.peek_buf[channel as usize] = None;
+        self.peek_flags[channel as usize] = false;
+    }
+}
+
+/// Baudrate divisor for `baud`, `(crystal_hz / prescaler) / (16 * baud)` rounded down
+///
+/// `prescaler` is the divider value, 1 or 4. 0 means `baud` can't be reached (or a zero
+/// argument), divisors above 0xFFFF don't fit DLL/DLH and mean it is too low.
+pub fn compute_divisor(crystal_hz: u32, prescaler: u32, baud: u32) -> u32 {
+    crystal_hz
+        .checked_div(prescaler)
+        .and_then(|clock| clock.checked_div(baud.saturating_mul(16)))
+        .unwrap_or(0)
+}
+
+/// Baudrate actually produced by `divisor`, `(crystal_hz / prescaler) / (16 * divisor)`
+///
+/// 0 for a zero divisor or prescaler.
+pub fn actual_baud(crystal_hz: u32, prescaler: u32, divisor: u32) -> u32 {
+    crystal_hz
+        .checked_div(prescaler)
+        .and_then(|clock| clock.checked_div(divisor.saturating_mul(16)))
+        .unwrap_or(0)
+}
+
+/// Clock prescaler selected by MCR bit 7
+fn prescaler(modem_control_register: u8) -> u32 {
+    match modem_control_register & 0x80 {
+        0 => 1,
+        _ => 4,
+    }
+}
+
+/// LCR[5:0] for a frame: word length, stop bits and parity
+fn line_format(data_length: u8, parity_select: Parity, stop_length: u8) -> u8 {
+    let mut line_control_register = match data_length {
+        5 => 0x00,
+        6 => 0x01,
+        7 => 0x02,
+        _ => 0x03,
+    };
+    if stop_length == 2 {
+        line_control_register |= 0x04;
+    }
+    match parity_select {
+        Parity::NoParity => {}
+        Parity::Odd => line_control_register |= 0x08,
+        Parity::Even => line_control_register |= 0x18,
+        Parity::ForcedParity1 => line_control_register |= 0x28,
+        Parity::ForcedParity0 => line_control_register |= 0x38,
+    }
+    line_control_register
+}
+
+/// FCR writes enabling the FIFOs with the shadowed FCR `fifo_control_register`
+///
+/// The trigger level and DMA bits only latch once FCR[0] is set, so when any are shadowed the
+/// enable bit goes out on its own first. Returns that optional write and the new FCR value,
+/// which is written last and becomes the shadow.
+fn fifo_enable_writes(fifo_control_register: u8) -> (Option<u8>, u8) {
+    let enable_first = (fifo_control_register & !0x01 != 0).then_some(0x01);
+    (enable_first, fifo_control_register | 0x01)
+}
+
+/// `compute_divisor` checked to fit DLL/DLH
+fn checked_divisor(crystal_hz: u32, prescaler: u32, baud: u32) -> Result<u16, ConfigError> {
+    if baud == 0 {
+        return Err(ConfigError::BaudrateTooLow);
+    }
+    match compute_divisor(crystal_hz, prescaler, baud) {
+        0 => Err(ConfigError::BaudrateTooHigh),
+        divisor => u16::try_from(divisor).map_err(|_| ConfigError::BaudrateTooLow),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal::i2c::{blocking::Operation, ErrorKind, ErrorType, SevenBitAddress};
+    use std::collections::VecDeque;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct MockError;
+
+    impl I2cError for MockError {
+        fn kind(&self) -> ErrorKind {
+            ErrorKind::Other
+        }
+    }
+
+    /// A single recorded bus transaction
+    #[derive(Debug, Clone, PartialEq)]
+    enum Op {
+        Read {
+            channel: usize,
+            reg: u8,
+            len: usize,
+        },
+        Write {
+            channel: usize,
+            reg: u8,
+            data: Vec<u8>,
+        },
+    }
+
+    /// Register level model of the SC16IS752 good enough to exercise the driver.
+    ///
+    /// Reads fall through to the register file unless a scripted value is queued for that
+    /// (channel, register) pair. RHR/THR are backed by per channel FIFOs and RXLVL reports
+    /// how many bytes are buffered.
+    #[derive(Debug, Default)]
+    struct MockI2c {
+        regs: [[u8; 16]; 2],
+        scripted: Vec<(usize, u8, VecDeque<u8>)>,
+        fcr: [u8; 2],
+        /// DLL/DLH, mapped over 0x00/0x01 while LCR[7] is set
+        divisor: [[u8; 2]; 2],
+        /// EFR (0x02) and XON1/XON2/XOFF1/XOFF2 (0x04-0x07), mapped while LCR is 0xBF
+        enhanced: [[u8; 8]; 2],
+        /// TCR/TLR, mapped over 0x06/0x07 while EFR[4] and MCR[2] are set
+        tcr_tlr: [[u8; 2]; 2],
+        rx: [VecDeque<u8>; 2],
+        tx: [Vec<u8>; 2],
+        /// Applied to bytes looped back from THR to RHR to simulate corruption
+        loopback_xor: u8,
+        /// Levels presented on the GPIO pins IODir configures as inputs
+        gpio_inputs: u8,
+        /// Number of upcoming transactions that fail with a bus error
+        fail_remaining: usize,
+        log: Vec<Op>,
+    }
+
+    impl MockI2c {
+        fn new() -> Self {
+            let mut mock = Self::default();
+            for regs in mock.regs.iter_mut() {
+                // THR and TSR empty
+                regs[0x05] = 0x60;
+                // TX FIFO empty
+                regs[0x08] = 64;
+            }
+            mock
+        }
+
+        /// Queue values to be returned by successive reads of `reg` on `channel`
+        fn script(&mut self, channel: Channel, reg: u8, values: &[u8]) {
+            let channel = channel as usize;
+            match self
+                .scripted
+                .iter_mut()
+                .find(|(c, r, _)| *c == channel && *r == reg)
+            {
+                Some((_, _, queue)) => queue.extend(values),
+                None => self
+                    .scripted
+                    .push((channel, reg, values.iter().copied().collect())),
+            }
+        }
+
+        fn reads_of(&self, channel: Channel, reg: u8) -> usize {
+            self.log
+                .iter()
+                .filter(|op| {
+                    matches!(op, Op::Read { channel: c, reg: r, .. } if *c == channel as usize && *r == reg)
+                })
+                .count()
+        }
+
+        fn fail(&mut self) -> Result<(), MockError> {
+            if self.fail_remaining > 0 {
+                self.fail_remaining -= 1;
+                return Err(MockError);
+            }
+            Ok(())
+        }
+
+        fn decode(command: u8) -> (usize, u8) {
+            (((command >> 1) & 0x03) as usize, command >> 3)
+        }
+
+        fn read_reg(&mut self, channel: usize, reg: u8) -> u8 {
+            if let Some((_, _, queue)) = self
+                .scripted
+                .iter_mut()
+                .find(|(c, r, q)| *c == channel && *r == reg && !q.is_empty())
+            {
+                return queue.pop_front().unwrap();
+            }
+            if let Some(value) = self.banked(channel, reg) {
+                return *value;
+            }
+            match reg {
+                0x00 => self.rx[channel].pop_front().unwrap_or(0),
+                0x05 => self.regs[channel][0x05] | !self.rx[channel].is_empty() as u8,
+                0x09 => self.rx[channel].len().min(64) as u8,
+                // Output latch on output pins, external level on input pins
+                0x0B => {
+                    let io_direction_register = self.regs[channel][0x0A];
+                    (self.regs[channel][0x0B] & io_direction_register)
+                        | (self.gpio_inputs & !io_direction_register)
+                }
+                _ => self.regs[channel][reg as usize],
+            }
+        }
+
+        /// The register `reg` maps to when the special or enhanced register sets are selected
+        fn banked(&mut self, channel: usize, reg: u8) -> Option<&mut u8> {
+            let line_control_register = self.regs[channel][0x03];
+            let reg = reg as usize;
+            if line_control_register & 0x80 != 0 && reg <= 0x01 {
+                Some(&mut self.divisor[channel][reg])
+            } else if line_control_register == 0xBF && matches!(reg, 0x02 | 0x04..=0x07) {
+                Some(&mut self.enhanced[channel][reg])
+            } else if line_control_register != 0xBF
+                && self.enhanced[channel][0x02] & 0x10 != 0
+                && self.regs[channel][0x04] & 0x04 != 0
+                && matches!(reg, 0x06 | 0x07)
+            {
+                Some(&mut self.tcr_tlr[channel][reg - 0x06])
+            } else {
+                None
+            }
+        }
+
+        fn write_reg(&mut self, channel: usize, reg: u8, value: u8) {
+            if let Some(banked) = self.banked(channel, reg) {
+                *banked = value;
+                return;
+            }
+            match reg {
+                0x00 => {
+                    self.tx[channel].push(value);
+                    if self.regs[channel][0x04] & 0x10 != 0 {
+                        self.rx[channel].push_back(value ^ self.loopback_xor);
+                    }
+                }
+                0x02 => {
+                    if value & 0x02 != 0 {
+                        self.rx[channel].clear();
+                    }
+                    if value & 0x04 != 0 {
+                        self.tx[channel].clear();
+                    }
+                    // Reset bits are self-clearing
+                    self.fcr[channel] = value & !0x06;
+                }
+                _ => self.regs[channel][reg as usize] = value,
+            }
+        }
+    }
+
+    impl ErrorType for MockI2c {
+        type Error = MockError;
+    }
+
+    impl I2c for MockI2c {
+        fn read(&mut self, _address: SevenBitAddress, _buffer: &mut [u8]) -> Result<(), MockError> {
+            unimplemented!()
+        }
+
+        fn write(&mut self, _address: SevenBitAddress, bytes: &[u8]) -> Result<(), MockError> {
+            self.fail()?;
+            let (channel, reg) = Self::decode(bytes[0]);
+            self.log.push(Op::Write {
+                channel,
+                reg,
+                data: bytes[1..].to_vec(),
+            });
+            for value in &bytes[1..] {
+                self.write_reg(channel, reg, *value);
+            }
+            Ok(())
+        }
+
+        fn write_iter<B>(&mut self, _address: SevenBitAddress, _bytes: B) -> Result<(), MockError>
+        where
+            B: IntoIterator<Item = u8>,
+        {
+            unimplemented!()
+        }
+
+        fn write_read(
+            &mut self,
+            _address: SevenBitAddress,
+            bytes: &[u8],
+            buffer: &mut [u8],
+        ) -> Result<(), MockError> {
+            self.fail()?;
+            let (channel, reg) = Self::decode(bytes[0]);
+            self.log.push(Op::Read {
+                channel,
+                reg,
+                len: buffer.len(),
+            });
+            for byte in buffer.iter_mut() {
+                *byte = self.read_reg(channel, reg);
+            }
+            Ok(())
+        }
+
+        fn write_iter_read<B>(
+            &mut self,
+            _address: SevenBitAddress,
+            _bytes: B,
+            _buffer: &mut [u8],
+        ) -> Result<(), MockError>
+        where
+            B: IntoIterator<Item = u8>,
+        {
+            unimplemented!()
+        }
+
+        fn transaction<'a>(
+            &mut self,
+            _address: SevenBitAddress,
+            _operations: &mut [Operation<'a>],
+        ) -> Result<(), MockError> {
+            unimplemented!()
+        }
+
+        fn transaction_iter<'a, O>(
+            &mut self,
+            _address: SevenBitAddress,
+            _operations: O,
+        ) -> Result<(), MockError>
+        where
+            O: IntoIterator<Item = Operation<'a>>,
+        {
+            unimplemented!()
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct MockDelay {
+        total_us: u64,
+    }
+
+    impl DelayUs for MockDelay {
+        type Error = core::convert::Infallible;
+
+        fn delay_us(&mut self, us: u32) -> Result<(), Self::Error> {
+            self.total_us += us as u64;
+            Ok(())
+        }
+    }
+
+    fn device() -> SC16IS752I2c<MockI2c> {
+        SC16IS752::new(0x48, MockI2c::new()).unwrap()
+    }
+
+    /// `device` with the FIFOs of both channels enabled, as `initialise` leaves them
+    fn fifo_device() -> SC16IS752<I2cTransport<MockI2c>> {
+        let mut device = device();
+        device.fifo_enable(Channel::A, true).unwrap();
+        device.fifo_enable(Channel::B, true).unwrap();
+        device.transport.i2c.log.clear();
+        device
+    }
+
+    #[test]
+    fn it_works() {
+        let result = 2 + 2;
+        assert_eq!(result, 4);
+    }
+
+    #[test]
+    fn gpio_directions_round_trip() {
+        use PinMode::{Input, Output};
+        let mut device = device();
+        let directions = [Output, Input, Output, Input, Output, Input, Input, Output];
+
+        device.gpio_set_directions(&directions).unwrap();
+        assert_eq!(device.transport.i2c.regs[0][0x0A], 0b1001_0101);
+        assert_eq!(device.gpio_get_directions().unwrap(), directions);
+    }
+
+    #[test]
+    fn read_up_to_collects_bursts_and_respects_cap() {
+        let mut device = fifo_device();
+        let mut delay = MockDelay::default();
+        device.transport.i2c.rx[0].extend(0..10);
+        // First burst of 3, a gap, then the remaining 7 show up
+        device.transport.i2c.script(Channel::A, 0x09, &[3, 0, 0]);
+
+        let data = device
+            .read_up_to(Channel::A, 8, Duration::from_millis(1), &mut delay)
+            .unwrap();
+        assert_eq!(data, (0..8).collect::<Vec<u8>>());
+        assert_eq!(delay.total_us, 200);
+        assert_eq!(device.transport.i2c.rx[0].len(), 2);
+    }
+
+    #[test]
+    fn read_up_to_returns_partial_on_timeout() {
+        let mut device = device();
+        let mut delay = MockDelay::default();
+        device.transport.i2c.rx[0].extend([1, 2]);
+
+        let data = device
+            .read_up_to(Channel::A, 8, Duration::from_millis(1), &mut delay)
+            .unwrap();
+        assert_eq!(data, [1, 2]);
+        assert_eq!(delay.total_us, 1000);
+    }
+
+    #[test]
+    fn reset_fifo_and_wait_polls_until_empty() {
+        let mut device = device();
+        let mut delay = MockDelay::default();
+        device.transport.i2c.rx[1].extend([1, 2, 3, 4, 5]);
+        // The reset takes a moment to show up in RXLVL
+        device.transport.i2c.script(Channel::B, 0x09, &[5, 0]);
+
+        assert!(device
+            .reset_fifo_and_wait(Channel::B, FifoSelect::Rx, &mut delay)
+            .unwrap());
+        assert_eq!(device.transport.i2c.reads_of(Channel::B, 0x09), 2);
+        assert_eq!(device.transport.i2c.reads_of(Channel::B, 0x08), 0);
+        assert_eq!(delay.total_us, 100);
+    }
+
+    #[test]
+    fn reset_fifo_and_wait_gives_up() {
+        let mut device = device();
+        let mut delay = MockDelay::default();
+        device.transport.i2c.regs[0][0x08] = 10;
+
+        assert!(!device
+            .reset_fifo_and_wait(Channel::A, FifoSelect::Both, &mut delay)
+            .unwrap());
+        assert_eq!(device.transport.i2c.reads_of(Channel::A, 0x08), 10);
+    }
+
+    #[test]
+    fn get_features_decodes_efcr() {
+        let mut device = device();
+        device.transport.i2c.regs[1][0x0F] = 0x31;
+
+        assert_eq!(
+            device.get_features(Channel::B).unwrap(),
+            FeaturesState {
+                auto_rs485_rts_output_inversion: true,
+                auto_rs485_direction_control: true,
+                multidrop: true,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn loopback_pattern_test_matches() {
+        let mut device = device();
+        let mut delay = MockDelay::default();
+        device.transport.i2c.regs[0][0x04] = 0x02;
+
+        assert!(device
+            .loopback_pattern_test(Channel::A, &[0x00, 0x55, 0xAA, 0xFF], &mut delay)
+            .unwrap());
+        assert_eq!(device.transport.i2c.regs[0][0x04], 0x02);
+    }
+
+    #[test]
+    fn loopback_pattern_test_detects_corruption() {
+        let mut device = device();
+        let mut delay = MockDelay::default();
+        device.transport.i2c.loopback_xor = 0x01;
+
+        assert!(!device
+            .loopback_pattern_test(Channel::B, &[0x00, 0x55, 0xAA, 0xFF], &mut delay)
+            .unwrap());
+    }
+
+    #[test]
+    fn set_tx_trigger_preserves_rx_nibble() {
+        let mut device = device();
+        device.transport.i2c.tcr_tlr[0][1] = 0x53;
+        device.transport.i2c.regs[0][0x03] = 0x03;
+        device.transport.i2c.regs[0][0x04] = 0x01;
+
+        device.set_tx_trigger(Channel::A, 8).unwrap();
+        assert_eq!(device.transport.i2c.tcr_tlr[0][1], 0x58);
+        // LCR, MCR and EFR are left as they were found
+        assert_eq!(device.transport.i2c.regs[0][0x03], 0x03);
+        assert_eq!(device.transport.i2c.regs[0][0x04], 0x01);
+        assert_eq!(device.transport.i2c.enhanced[0][0x02], 0x00);
+    }
+
+    #[test]
+    fn set_tx_trigger_rejects_out_of_range() {
+        let mut device = device();
+        for level in [0, 16] {
+            assert_eq!(
+                device.set_tx_trigger(Channel::A, level),
+                Err(Error::Config(ConfigError::InvalidTriggerLevel))
+            );
+        }
+        assert!(device.transport.i2c.log.is_empty());
+    }
+
+    #[test]
+    fn ring_and_carrier_detect_decode_msr() {
+        let mut device = device();
+        device
+            .transport
+            .i2c
+            .script(Channel::B, 0x06, &[0x04, 0x40, 0x80, 0x04]);
+
+        assert!(device.ring_detected(Channel::B).unwrap());
+        assert!(!device.ring_detected(Channel::B).unwrap());
+        assert!(device.carrier_detected(Channel::B).unwrap());
+        assert!(!device.carrier_detected(Channel::B).unwrap());
+    }
+
+    #[test]
+    fn write_fifo_burst_respects_chunk_size() {
+        let mut device = device();
+        let data: Vec<u8> = (0..64).collect();
+        device.set_max_i2c_chunk(16).unwrap();
+
+        device.write_fifo_burst(Channel::B, &data).unwrap();
+        assert_eq!(device.transport.i2c.log.len(), 4);
+        assert!(device
+            .transport
+            .i2c
+            .log
+            .iter()
+            .all(|op| matches!(op, Op::Write { channel: 1, reg: 0, data } if data.len() == 16)));
+        assert_eq!(device.transport.i2c.tx[1], data);
+    }
+
+    #[test]
+    fn read_fifo_burst_respects_chunk_size() {
+        let mut device = device();
+        device.transport.i2c.rx[0].extend(0..40);
+        device.set_max_i2c_chunk(32).unwrap();
+
+        let mut buf = [0u8; 40];
+        device.read_fifo_burst(Channel::A, &mut buf).unwrap();
+        assert_eq!(buf.to_vec(), (0..40).collect::<Vec<u8>>());
+        assert_eq!(device.transport.i2c.reads_of(Channel::A, 0x00), 2);
+    }
+
+    #[test]
+    fn set_max_i2c_chunk_rejects_out_of_range() {
+        let mut device = device();
+        assert_eq!(
+            device.set_max_i2c_chunk(0),
+            Err(ConfigError::InvalidChunkSize)
+        );
+        assert_eq!(
+            device.set_max_i2c_chunk(65),
+            Err(ConfigError::InvalidChunkSize)
+        );
+    }
+
+    #[test]
+    fn recover_bus_succeeds_within_retries() {
+        let mut device = device();
+        let mut delay = MockDelay::default();
+        device.transport.i2c.fail_remaining = 3;
+
+        device.recover_bus(3, &mut delay).unwrap();
+        assert_eq!(delay.total_us, 1000 + 2000 + 4000);
+    }
+
+    #[test]
+    fn recover_bus_reports_wedged_bus() {
+        let mut device = device();
+        let mut delay = MockDelay::default();
+        device.transport.i2c.fail_remaining = 4;
+
+        assert_eq!(device.recover_bus(3, &mut delay), Err(Error::BusWedged));
+        assert_eq!(device.transport.i2c.fail_remaining, 0);
+    }
+
+    #[test]
+    fn fifo_enable_writes_shadow_after_enable_bit() {
+        let mut device = device();
+        device.set_variant(Variant::SC16IS750);
+        // RX trigger bits set in the shadow, IIR reads back something unrelated
+        device.fcr[0] = 0xC0;
+        device.transport.i2c.regs[0][0x02] = 0xC1;
+
+        device.fifo_enable(Channel::A, true).unwrap();
+        assert_eq!(
+            device.transport.i2c.log,
+            [
+                Op::Write {
+                    channel: 0,
+                    reg: 0x02,
+                    data: vec![0x01]
+                },
+                Op::Write {
+                    channel: 0,
+                    reg: 0x02,
+                    data: vec![0xC1]
+                },
+            ]
+        );
+
+        device.fifo_enable(Channel::A, false).unwrap();
+        assert_eq!(device.transport.i2c.fcr[0], 0xC0);
+    }
+
+    #[test]
+    fn fifo_enable_without_trigger_bits_is_a_single_write() {
+        let mut device = device();
+        device.fifo_enable(Channel::B, true).unwrap();
+        assert_eq!(device.transport.i2c.log.len(), 1);
+        assert_eq!(device.transport.i2c.fcr[1], 0x01);
+    }
+
+    #[test]
+    fn get_software_flow_control_decodes_efr_and_chars() {
+        let mut device = device();
+        device.transport.i2c.regs[1][0x03] = 0x1B;
+        device.transport.i2c.enhanced[1][0x02] = 0x1A;
+        device.transport.i2c.enhanced[1][0x04] = 0x11;
+        device.transport.i2c.enhanced[1][0x06] = 0x13;
+
+        assert_eq!(
+            device.get_software_flow_control(Channel::B).unwrap(),
+            (SwFlowMode::XonXoff1, 0x11, 0x13)
+        );
+        assert_eq!(device.transport.i2c.regs[1][0x03], 0x1B);
+    }
+
+    #[test]
+    fn sw_flow_mode_decodes_other_combinations() {
+        assert_eq!(SwFlowMode::from_bits(0xF5), SwFlowMode::XonXoff2);
+        assert_eq!(SwFlowMode::from_bits(0x08), SwFlowMode::Other(0x08));
+    }
+
+    #[test]
+    fn set_flow_thresholds_bytes_composes_tcr() {
+        let mut device = device();
+        device
+            .set_flow_thresholds_bytes(Channel::A, 48, 16)
+            .unwrap();
+        assert_eq!(device.transport.i2c.tcr_tlr[0][0], 0x4C);
+        assert_eq!(device.transport.i2c.regs[0][0x04], 0x00);
+    }
+
+    #[test]
+    fn set_flow_thresholds_bytes_rejects_invalid_levels() {
+        let mut device = device();
+        for (halt, resume) in [(16, 48), (32, 32), (64, 16), (30, 16), (32, 10)] {
+            assert_eq!(
+                device.set_flow_thresholds_bytes(Channel::A, halt, resume),
+                Err(Error::Config(ConfigError::InvalidFlowThreshold))
+            );
+        }
+        assert!(device.transport.i2c.log.is_empty());
+    }
+
+    #[test]
+    fn set_flow_trigger_levels_unlocks_tcr_and_restores() {
+        let mut device = device();
+        device.transport.i2c.regs[0][0x03] = 0x03;
+        device.transport.i2c.regs[0][0x04] = 0x01;
+
+        device.set_flow_trigger_levels(Channel::A, 2, 12).unwrap();
+        assert_eq!(device.transport.i2c.tcr_tlr[0][0], 0x2C);
+        let writes: Vec<(u8, Vec<u8>)> = device
+            .transport
+            .i2c
+            .log
+            .iter()
+            .filter_map(|op| match op {
+                Op::Write { reg, data, .. } => Some((*reg, data.clone())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            writes,
+            [
+                (0x03, vec![0xBF]),
+                (0x02, vec![0x10]),
+                (0x03, vec![0x03]),
+                (0x04, vec![0x05]),
+                (0x06, vec![0x2C]),
+                (0x04, vec![0x01]),
+                (0x03, vec![0xBF]),
+                (0x02, vec![0x00]),
+                (0x03, vec![0x03]),
+            ]
+        );
+        assert_eq!(
+            device.set_flow_trigger_levels(Channel::A, 16, 2),
+            Err(Error::Config(ConfigError::InvalidFlowThreshold))
+        );
+        assert_eq!(
+            device.set_flow_trigger_levels(Channel::A, 8, 4),
+            Err(Error::Config(ConfigError::InvalidFlowThreshold))
+        );
+    }
+
+    #[test]
+    fn rx_headroom_subtracts_rxlvl_from_halt_level() {
+        let mut device = device();
+        device.transport.i2c.tcr_tlr[1][0] = 0x4C;
+        device.transport.i2c.rx[1].extend([0; 20]);
+
+        assert_eq!(device.rx_headroom(Channel::B).unwrap(), 28);
+        device.transport.i2c.rx[1].extend([0; 40]);
+        assert_eq!(device.rx_headroom(Channel::B).unwrap(), 0);
+    }
+
+    #[test]
+    fn clear_peek_discards_stale_byte() {
+        let mut device = device();
+        device.transport.i2c.rx[0].extend([0x55, 0x66]);
+        device.peek_buf[0] = Some(0xAA);
+        device.peek_flags[0] = true;
+
+        device.clear_peek(Channel::A);
+        assert_eq!(device.read_byte(Channel::A).unwrap(), Some(0x55));
+        assert_eq!(device.transport.i2c.reads_of(Channel::A, 0x00), 1);
+    }
+
+    #[test]
+    fn peeked_byte_is_read_first() {
+        let mut device = device();
+        device.transport.i2c.rx[0].push_back(0x55);
+        device.peek_buf[0] = Some(0xAA);
+        device.peek_flags[0] = true;
+
+        assert_eq!(device.read_byte(Channel::A).unwrap(), Some(0xAA));
+        assert!(device.transport.i2c.log.is_empty());
+        assert_eq!(device.read_byte(Channel::A).unwrap(), Some(0x55));
+    }
+
+    #[test]
+    fn rx_fifo_reset_clears_peek() {
+        let mut device = device();
+        device.peek_buf[1] = Some(0xAA);
+        device.peek_flags[1] = true;
+
+        device.fifo_reset(Channel::B, true, false).unwrap();
+        assert_eq!(device.read_byte(Channel::B).unwrap(), None);
+    }
+
+    #[test]
+    fn send_chunks_through_a_draining_fifo() {
+        let mut device = device();
+        let mut delay = MockDelay::default();
+        let data: Vec<u8> = (0..200).collect();
+        for _ in 0..13 {
+            device.transport.i2c.script(Channel::A, 0x08, &[0, 16]);
+        }
+
+        device.send(Channel::A, &data, &mut delay).unwrap();
+        assert_eq!(device.transport.i2c.tx[0], data);
+        let bursts = device
+            .transport
+            .i2c
+            .log
+            .iter()
+            .filter(|op| matches!(op, Op::Write { reg: 0, .. }))
+            .count();
+        assert_eq!(bursts, 13);
+        assert_eq!(delay.total_us, 13 * 100);
+    }
+
+    #[test]
+    fn send_times_out_on_a_stuck_fifo() {
+        let mut device = device();
+        let mut delay = MockDelay::default();
+        device.transport.i2c.regs[1][0x08] = 0;
+
+        assert_eq!(
+            device.send(Channel::B, &[1, 2, 3], &mut delay),
+            Err(Error::Timeout)
+        );
+        assert!(device.transport.i2c.tx[1].is_empty());
+    }
+
+    #[test]
+    fn send_waits_out_cts_hold_off() {
+        let mut device = device();
+        let mut delay = MockDelay::default();
+        // Auto-CTS on, CTS de-asserted for longer than the stall budget
+        device.transport.i2c.enhanced[0][0x02] = 0x90;
+        device.transport.i2c.script(Channel::A, 0x08, &[0; 1500]);
+
+        device.send(Channel::A, &[1, 2, 3], &mut delay).unwrap();
+        assert_eq!(device.transport.i2c.tx[0], [1, 2, 3]);
+    }
+
+    #[test]
+    fn transmitter_empty_decodes_temt() {
+        let mut device = device();
+        device
+            .transport
+            .i2c
+            .script(Channel::A, 0x05, &[0x60, 0x60, 0x20, 0x20]);
+
+        assert!(device.tx_ready(Channel::A).unwrap());
+        assert!(device.transmitter_empty(Channel::A).unwrap());
+        assert!(device.tx_ready(Channel::A).unwrap());
+        assert!(!device.transmitter_empty(Channel::A).unwrap());
+    }
+
+    /// Single byte register writes in order as (channel, register, value)
+    fn writes(device: &SC16IS752<I2cTransport<MockI2c>>) -> Vec<(usize, u8, u8)> {
+        device
+            .transport
+            .i2c
+            .log
+            .iter()
+            .filter_map(|op| match op {
+                Op::Write { channel, reg, data } if data.len() == 1 => {
+                    Some((*channel, *reg, data[0]))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn set_baudrate_with_fifo_reset_resets_after_divisor() {
+        let mut device = device();
+        device.transport.i2c.regs[0][0x03] = 0x03;
+
+        device
+            .set_baudrate_with_fifo_reset(Channel::A, 9600)
+            .unwrap();
+        assert_eq!(
+            writes(&device),
+            [
+                (0, 0x03, 0x83),
+                (0, 0x00, 12),
+                (0, 0x01, 0),
+                (0, 0x03, 0x03),
+                (0, 0x02, 0x06),
+            ]
+        );
+    }
+
+    #[test]
+    fn set_baudrate_resets_fifos_only_when_enabled() {
+        let mut device = device();
+        device.set_baudrate(Channel::B, 9600).unwrap();
+        assert_eq!(writes(&device).len(), 4);
+
+        device.set_baud_change_fifo_reset(true);
+        device
+            .set_baudrate_with_fifo_reset(Channel::B, 9600)
+            .unwrap();
+        assert_eq!(writes(&device).len(), 4 + 5);
+    }
+
+    #[test]
+    fn supported_baud_rates_for_default_crystal() {
+        let device = device();
+        let rates: Vec<(u32, i32)> = device.supported_baud_rates(Channel::A).collect();
+
+        assert_eq!(rates.len(), STANDARD_BAUD_RATES.len());
+        assert!(rates.contains(&(9600, 0)));
+        assert!(rates.iter().all(|(_, error)| error.abs() <= 1));
+    }
+
+    #[test]
+    fn rs485_manual_drives_rts_around_the_burst() {
+        let mut device = device();
+        device.transport.i2c.regs[0][0x0F] = 0x10;
+        // THR empty for both writes, then TEMT goes high on the second poll
+        device
+            .transport
+            .i2c
+            .script(Channel::A, 0x05, &[0x20, 0x20, 0x20, 0x60]);
+
+        device
+            .rs485_manual(Channel::A, PinState::High, &[0xAB, 0xCD])
+            .unwrap();
+        let log = &device.transport.i2c.log;
+        let mcr_writes: Vec<usize> = (0..log.len())
+            .filter(|i| matches!(&log[*i], Op::Write { reg: 0x04, .. }))
+            .collect();
+        let first_thr = log
+            .iter()
+            .position(|op| matches!(op, Op::Write { reg: 0x00, .. }))
+            .unwrap();
+        let last_lsr = log
+            .iter()
+            .rposition(|op| matches!(op, Op::Read { reg: 0x05, .. }))
+            .unwrap();
+
+        assert_eq!(mcr_writes.len(), 2);
+        assert!(mcr_writes[0] < first_thr);
+        assert!(mcr_writes[1] > last_lsr);
+        assert_eq!(device.transport.i2c.reads_of(Channel::A, 0x05), 4);
+        // DE active high means RTS pin high while sending, i.e. MCR[1] clear, then set
+        assert_eq!(
+            log[mcr_writes[0]],
+            Op::Write {
+                channel: 0,
+                reg: 0x04,
+                data: vec![0x00]
+            }
+        );
+        assert_eq!(
+            log[mcr_writes[1]],
+            Op::Write {
+                channel: 0,
+                reg: 0x04,
+                data: vec![0x02]
+            }
+        );
+        // Auto direction control switched off
+        assert_eq!(device.transport.i2c.regs[0][0x0F], 0x00);
+        assert_eq!(device.transport.i2c.tx[0], [0xAB, 0xCD]);
+    }
+
+    #[test]
+    fn write_rs485_drops_de_when_temt_never_sets() {
+        let mut device = device();
+        // THR empties but the shift register never does
+        device.transport.i2c.regs[0][0x05] = 0x20;
+
+        assert_eq!(
+            device.write_rs485(Channel::A, GPIO::GPIO5, &[0x01]),
+            Err(Error::Timeout)
+        );
+        assert_eq!(writes(&device).last(), Some(&(0, 0x0B, 0x00)));
+        assert_eq!(
+            device.gpio_get_pin_state(GPIO::GPIO5).unwrap(),
+            PinState::Low
+        );
+    }
+
+    #[test]
+    fn write_rs485_drops_de_when_write_fails() {
+        let mut device = device();
+        // THR never empties, so the first byte times out
+        device.transport.i2c.regs[1][0x05] = 0x00;
+
+        assert_eq!(
+            device.write_rs485(Channel::B, GPIO::GPIO1, &[0x01]),
+            Err(Error::Timeout)
+        );
+        assert!(device.transport.i2c.tx[1].is_empty());
+        assert_eq!(writes(&device).last(), Some(&(0, 0x0B, 0x00)));
+    }
+
+    #[test]
+    fn rs485_manual_restores_rts_when_temt_never_sets() {
+        let mut device = device();
+        device.transport.i2c.regs[0][0x05] = 0x20;
+
+        assert_eq!(
+            device.rs485_manual(Channel::A, PinState::High, &[0x01]),
+            Err(Error::Timeout)
+        );
+        assert_eq!(writes(&device).last(), Some(&(0, 0x04, 0x02)));
+    }
+
+    #[test]
+    fn write_rs485_holds_de_gpio_until_temt() {
+        let mut device = device();
+        // THR empty for both writes, then TEMT goes high on the second poll
+        device
+            .transport
+            .i2c
+            .script(Channel::B, 0x05, &[0x20, 0x20, 0x20, 0x60]);
+
+        device
+            .write_rs485(Channel::B, GPIO::GPIO2, &[0x12, 0x34])
+            .unwrap();
+        let log = &device.transport.i2c.log;
+        let io_state_writes: Vec<usize> = (0..log.len())
+            .filter(|i| matches!(&log[*i], Op::Write { reg: 0x0B, .. }))
+            .collect();
+        let first_thr = log
+            .iter()
+            .position(|op| matches!(op, Op::Write { reg: 0x00, .. }))
+            .unwrap();
+        let temt = log
+            .iter()
+            .rposition(|op| matches!(op, Op::Read { reg: 0x05, .. }))
+            .unwrap();
+
+        assert_eq!(device.transport.i2c.regs[0][0x0A], 0x04);
+        assert_eq!(io_state_writes.len(), 2);
+        assert!(io_state_writes[0] < first_thr);
+        assert!(io_state_writes[1] > temt);
+        assert_eq!(
+            log[io_state_writes[0]],
+            Op::Write {
+                channel: 0,
+                reg: 0x0B,
+                data: vec![0x04]
+            }
+        );
+        assert_eq!(
+            log[io_state_writes[1]],
+            Op::Write {
+                channel: 0,
+                reg: 0x0B,
+                data: vec![0x00]
+            }
+        );
+        assert_eq!(device.transport.i2c.tx[1], [0x12, 0x34]);
+    }
+
+    #[test]
+    fn last_interrupt_is_kept_without_rereading_iir() {
+        let mut device = device();
+        device.transport.i2c.script(Channel::B, 0x02, &[0x04]);
+        assert_eq!(device.last_interrupt(Channel::B), None);
+
+        assert_eq!(
+            device.isr(Channel::B).unwrap(),
+            InterruptEvent::RHR_INTERRUPT
+        );
+        assert_eq!(
+            device.last_interrupt(Channel::B),
+            Some(InterruptEvent::RHR_INTERRUPT)
+        );
+        assert_eq!(device.last_interrupt(Channel::A), None);
+        assert_eq!(device.transport.i2c.reads_of(Channel::B, 0x02), 1);
+    }
+
+    #[test]
+    fn gpio_set_interrupt_toggles_only_its_pin() {
+        let mut device = device();
+        device.transport.i2c.regs[0][0x0C] = 0x81;
+
+        device.gpio_set_interrupt(GPIO::GPIO3, true).unwrap();
+        assert_eq!(device.transport.i2c.regs[0][0x0C], 0x89);
+        device.gpio_set_interrupt(GPIO::GPIO6, true).unwrap();
+        assert_eq!(device.transport.i2c.regs[0][0x0C], 0xC9);
+        device.gpio_set_interrupt(GPIO::GPIO7, false).unwrap();
+        assert_eq!(device.transport.i2c.regs[0][0x0C], 0x49);
+    }
+
+    #[test]
+    fn gpio_set_pin_state_keeps_other_outputs_and_reads_live_inputs() {
+        let mut device = device();
+        // GPIO0-3 outputs with GPIO1 and GPIO3 high, GPIO4-7 inputs
+        device.gpio_set_port_mode(0x0F).unwrap();
+        device.gpio_set_port_state(0x0A).unwrap();
+        device.transport.i2c.gpio_inputs = 0x5F;
+
+        device
+            .gpio_set_pin_state(GPIO::GPIO0, PinState::High)
+            .unwrap();
+        assert_eq!(device.transport.i2c.regs[0][0x0B], 0x0B);
+        device
+            .gpio_set_pin_state(GPIO::GPIO3, PinState::Low)
+            .unwrap();
+        assert_eq!(device.transport.i2c.regs[0][0x0B], 0x03);
+
+        assert_eq!(
+            device.gpio_get_pin_state(GPIO::GPIO4).unwrap(),
+            PinState::High
+        );
+        assert_eq!(
+            device.gpio_get_pin_state(GPIO::GPIO5).unwrap(),
+            PinState::Low
+        );
+        assert_eq!(
+            device.gpio_get_pin_state(GPIO::GPIO3).unwrap(),
+            PinState::Low
+        );
+        assert_eq!(device.gpio_get_port_state().unwrap(), 0x53);
+    }
+
+    #[test]
+    fn gpio_set_pull_accepts_none_and_rejects_pulls() {
+        let mut device = device();
+        device.gpio_set_pull(GPIO::GPIO2, Pull::None).unwrap();
+
+        for variant in [
+            Variant::SC16IS750,
+            Variant::SC16IS752,
+            Variant::SC16IS760,
+            Variant::SC16IS762,
+        ] {
+            device.set_variant(variant);
+            assert_eq!(
+                device.gpio_set_pull(GPIO::GPIO2, Pull::Up),
+                Err(Error::UnsupportedFeature)
+            );
+            assert_eq!(
+                device.gpio_set_pull(GPIO::GPIO5, Pull::Down),
+                Err(Error::UnsupportedFeature)
+            );
+        }
+        assert!(device.transport.i2c.log.is_empty());
+    }
+
+    #[test]
+    fn apply_register_table_writes_in_order() {
+        let mut device = device();
+        let table = [(0x03, 0x80), (0x00, 0x0C), (0x01, 0x00), (0x03, 0x03)];
+
+        device.apply_register_table(Channel::B, &table).unwrap();
+        assert_eq!(writes(&device), table.map(|(reg, value)| (1, reg, value)));
+    }
+
+    #[test]
+    fn apply_register_table_rejects_bad_address() {
+        let mut device = device();
+        assert_eq!(
+            device.apply_register_table(Channel::A, &[(0x03, 0x03), (0x10, 0x00)]),
+            Err(Error::Config(ConfigError::InvalidRegister))
+        );
+        assert!(device.transport.i2c.log.is_empty());
+    }
+
+    #[test]
+    fn enable_features_sets_and_clears_the_feature_bit() {
+        let mut device = device();
+        device.transport.i2c.regs[0][0x0F] = 0x01;
+
+        device
+            .enable_features(
+                Channel::A,
+                FeaturesRegister::AutoRs485DirectionControl,
+                true,
+            )
+            .unwrap();
+        device
+            .enable_features(Channel::A, FeaturesRegister::TxDisable, true)
+            .unwrap();
+        device
+            .enable_features(
+                Channel::A,
+                FeaturesRegister::AutoRs485DirectionControl,
+                false,
+            )
+            .unwrap();
+        device
+            .enable_features(Channel::A, FeaturesRegister::TxDisable, false)
+            .unwrap();
+        assert_eq!(
+            writes(&device),
+            [
+                (0, 0x0F, 0x11),
+                (0, 0x0F, 0x15),
+                (0, 0x0F, 0x05),
+                (0, 0x0F, 0x01),
+            ]
+        );
+    }
+
+    #[test]
+    fn bytes_transmitted_counts_every_write_path() {
+        let mut device = device();
+        device.write(Channel::A, &[0; 10]).unwrap();
+        assert_eq!(device.bytes_transmitted(Channel::A), 10);
+
+        device.write_fifo_burst(Channel::A, &[0; 20]).unwrap();
+        assert_eq!(device.bytes_transmitted(Channel::A), 30);
+        assert_eq!(device.bytes_transmitted(Channel::B), 0);
+    }
+
+    #[test]
+    fn peek_then_read_returns_peeked_byte_first() {
+        let mut device = device();
+        device.transport.i2c.rx[0].extend([1, 2, 3]);
+
+        assert_eq!(device.peek(Channel::A).unwrap(), Some(1));
+        assert_eq!(device.peek(Channel::A).unwrap(), Some(1));
+        assert_eq!(device.transport.i2c.rx[0], [2, 3]);
+        assert_eq!(device.read_byte(Channel::A).unwrap(), Some(1));
+        assert_eq!(device.read_byte(Channel::A).unwrap(), Some(2));
+    }
+
+    #[test]
+    fn peek_is_returned_by_read_up_to() {
+        let mut device = device();
+        let mut delay = MockDelay::default();
+        device.transport.i2c.rx[1].extend([1, 2, 3]);
+
+        assert_eq!(device.peek(Channel::B).unwrap(), Some(1));
+        let data = device
+            .read_up_to(Channel::B, 8, Duration::from_micros(100), &mut delay)
+            .unwrap();
+        assert_eq!(data, [1, 2, 3]);
+    }
+
+    #[test]
+    fn peek_on_empty_fifo_returns_none() {
+        let mut device = device();
+        assert_eq!(device.peek(Channel::B).unwrap(), None);
+        device.transport.i2c.rx[1].push_back(7);
+        assert_eq!(device.peek(Channel::B).unwrap(), Some(7));
+    }
+
+    #[test]
+    fn check_and_clear_overrun_resets_rx_fifo() {
+        let mut device = device();
+        device.transport.i2c.rx[0].extend([1, 2, 3]);
+        device.transport.i2c.script(Channel::A, 0x05, &[0x61, 0x63]);
+
+        assert!(!device.check_and_clear_overrun(Channel::A).unwrap());
+        assert!(writes(&device).is_empty());
+        assert!(device.check_and_clear_overrun(Channel::A).unwrap());
+        assert_eq!(writes(&device), [(0, 0x02, 0x02)]);
+        assert!(device.transport.i2c.rx[0].is_empty());
+    }
+
+    #[test]
+    fn read_all_reads_exactly_the_fifo_level() {
+        let mut device = fifo_device();
+        device.transport.i2c.rx[0].extend([1, 2, 3, 4, 5]);
+
+        assert_eq!(device.read_all(Channel::A).unwrap(), [1, 2, 3, 4, 5]);
+        assert_eq!(device.transport.i2c.reads_of(Channel::A, 0x00), 5);
+    }
+
+    #[test]
+    fn read_all_reads_rxlvl_once() {
+        let mut device = fifo_device();
+        device.transport.i2c.rx[0].extend(0..10);
+
+        assert_eq!(device.read_all(Channel::A).unwrap().len(), 10);
+        assert_eq!(device.transport.i2c.reads_of(Channel::A, 0x09), 1);
+
+        device.transport.i2c.log.clear();
+        device.transport.i2c.rx[0].extend(0..10);
+        assert_eq!(device.read(Channel::A, 8).unwrap().len(), 8);
+        assert_eq!(device.transport.i2c.reads_of(Channel::A, 0x09), 1);
+    }
+
+    #[test]
+    fn rx_iter_yields_exactly_the_buffered_bytes() {
+        let mut device = fifo_device();
+        device.transport.i2c.rx[1].extend(b"hello");
+        device.peek(Channel::B).unwrap();
+        device.transport.i2c.log.clear();
+
+        let bytes: Vec<u8> = device
+            .rx_iter(Channel::B)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(bytes, b"hello");
+        assert_eq!(device.transport.i2c.reads_of(Channel::B, 0x09), 1);
+        assert_eq!(device.rx_iter(Channel::B).count(), 0);
+    }
+
+    #[test]
+    fn rx_iter_snapshots_the_level_at_creation() {
+        let mut device = fifo_device();
+        device.transport.i2c.rx[0].extend([1, 2]);
+        device.transport.i2c.log.clear();
+
+        drop(device.rx_iter(Channel::A));
+        assert_eq!(device.transport.i2c.reads_of(Channel::A, 0x09), 1);
+
+        let mut iter = device.rx_iter(Channel::A);
+        assert_eq!(iter.next(), Some(Ok(1)));
+        assert_eq!(iter.next(), Some(Ok(2)));
+        assert_eq!(iter.next(), None);
+        drop(iter);
+        assert_eq!(device.transport.i2c.reads_of(Channel::A, 0x09), 2);
+    }
+
+    #[test]
+    fn rx_iter_surfaces_bus_error_and_stops() {
+        let mut device = fifo_device();
+        device.transport.i2c.rx[0].extend([1, 2, 3]);
+        device.transport.i2c.fail_remaining = 1;
+
+        let mut iter = device.rx_iter(Channel::A);
+        assert_eq!(iter.next(), Some(Err(Error::Bus(MockError))));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn read_limits_to_quantity_and_level() {
+        let mut device = fifo_device();
+        device.transport.i2c.rx[1].extend([1, 2, 3, 4, 5]);
+
+        assert_eq!(device.read(Channel::B, 3).unwrap(), [1, 2, 3]);
+        assert_eq!(device.transport.i2c.reads_of(Channel::B, 0x00), 3);
+        assert_eq!(device.read(Channel::B, 10).unwrap(), [4, 5]);
+        assert_eq!(device.transport.i2c.reads_of(Channel::B, 0x00), 5);
+    }
+
+    #[test]
+    fn read_counts_the_peeked_byte() {
+        let mut device = fifo_device();
+        device.transport.i2c.rx[0].extend([1, 2, 3]);
+        device.peek(Channel::A).unwrap();
+
+        assert_eq!(device.read_all(Channel::A).unwrap(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn detect_variant_classifies_missing_channel_b_as_750() {
+        let mut device = device();
+        device.transport.i2c.regs[0][0x07] = 0x42;
+        // Saved value, then channel B ignores the probe write
+        device.transport.i2c.script(Channel::B, 0x07, &[0xFF, 0xFF]);
+
+        assert_eq!(device.detect_variant().unwrap(), Variant::SC16IS750);
+        assert_eq!(device.variant(), Variant::SC16IS750);
+        assert_eq!(device.transport.i2c.regs[0][0x07], 0x42);
+
+        let log_len = device.transport.i2c.log.len();
+        assert!(device.ping().unwrap());
+        assert!(device.transport.i2c.log[log_len..]
+            .iter()
+            .all(|op| !matches!(
+                op,
+                Op::Read { channel: 1, .. } | Op::Write { channel: 1, .. }
+            )));
+    }
+
+    #[test]
+    fn detect_variant_keeps_dual_channel_and_irda_family() {
+        let mut device = device();
+        device.transport.i2c.regs[1][0x07] = 0x24;
+        assert_eq!(device.detect_variant().unwrap(), Variant::SC16IS752);
+        assert_eq!(device.transport.i2c.regs[1][0x07], 0x24);
+
+        device.set_variant(Variant::SC16IS760);
+        assert_eq!(device.detect_variant().unwrap(), Variant::SC16IS762);
+    }
+
+    #[test]
+    fn is_present_restores_scratchpad() {
+        let mut device = device();
+        device.transport.i2c.regs[0][0x07] = 0x3C;
+
+        assert!(device.is_present().unwrap());
+        assert_eq!(device.transport.i2c.regs[0][0x07], 0x3C);
+        assert_eq!(writes(&device), [(0, 0x07, 0xC3), (0, 0x07, 0x3C)]);
+
+        // SPR stuck at its old value
+        device.transport.i2c.script(Channel::A, 0x07, &[0x3C, 0x3C]);
+        assert!(!device.is_present().unwrap());
+        assert_eq!(device.transport.i2c.regs[0][0x07], 0x3C);
+    }
+
+    #[test]
+    fn is_present_leaves_xoff2_alone() {
+        let mut device = device();
+        device.transport.i2c.regs[0][0x03] = 0xBF;
+        device.transport.i2c.regs[0][0x07] = 0x11;
+        device.transport.i2c.enhanced[0][0x07] = 0x13;
+
+        assert!(device.is_present().unwrap());
+        assert_eq!(device.transport.i2c.enhanced[0][0x07], 0x13);
+        assert_eq!(device.transport.i2c.regs[0][0x07], 0x11);
+        assert_eq!(device.transport.i2c.regs[0][0x03], 0xBF);
+    }
+
+    #[test]
+    fn is_present_leaves_tlr_alone() {
+        let mut device = device();
+        device.transport.i2c.enhanced[0][0x02] = 0x10;
+        device.transport.i2c.regs[0][0x04] = 0x04;
+        device.transport.i2c.regs[0][0x07] = 0x11;
+        device.transport.i2c.tcr_tlr[0][1] = 0x88;
+
+        assert!(device.is_present().unwrap());
+        assert_eq!(device.transport.i2c.tcr_tlr[0][1], 0x88);
+        assert_eq!(device.transport.i2c.regs[0][0x07], 0x11);
+        assert_eq!(device.transport.i2c.regs[0][0x04], 0x04);
+    }
+
+    #[test]
+    fn ping_with_patterns_walks_and_restores() {
+        let mut device = device();
+        let walking_ones: Vec<u8> = (0..8).map(|bit| 1 << bit).collect();
+        device.transport.i2c.regs[0][0x07] = 0x42;
+        device.transport.i2c.regs[1][0x07] = 0x24;
+
+        assert!(device.ping_with_patterns(&walking_ones).unwrap());
+        assert_eq!(device.transport.i2c.reads_of(Channel::A, 0x07), 9);
+        assert_eq!(device.transport.i2c.reads_of(Channel::B, 0x07), 9);
+        assert_eq!(device.transport.i2c.regs[0][0x07], 0x42);
+        assert_eq!(device.transport.i2c.regs[1][0x07], 0x24);
+    }
+
+    #[test]
+    fn ping_with_patterns_fails_on_mismatch() {
+        let mut device = device();
+        // Saved value, then bit 2 reads back stuck low
+        device
+            .transport
+            .i2c
+            .script(Channel::A, 0x07, &[0x42, 0x01, 0x02, 0x00]);
+
+        assert!(!device
+            .ping_with_patterns(&[0x01, 0x02, 0x04, 0x08])
+            .unwrap());
+        assert_eq!(device.transport.i2c.reads_of(Channel::B, 0x07), 0);
+        assert_eq!(writes(&device).last(), Some(&(0, 0x07, 0x42)));
+    }
+
+    #[test]
+    fn uart_handle_write_accepts_what_fits() {
+        let mut device = device();
+        device.transport.i2c.regs[1][0x08] = 3;
+
+        let mut uart = device.channel(Channel::B);
+        assert_eq!(uart.write(b"hello").unwrap(), 3);
+        assert_eq!(device.transport.i2c.tx[1], b"hel");
+        assert_eq!(device.bytes_transmitted(Channel::B), 3);
+    }
+
+    #[test]
+    fn uart_handle_formats_into_thr() {
+        use core::fmt::Write;
+        let mut device = device();
+
+        let mut uart = device.channel(Channel::A);
+        write!(uart, "temp={}C\r\n", 21).unwrap();
+        assert_eq!(uart.last_error(), None);
+        assert_eq!(device.transport.i2c.tx[0], b"temp=21C\r\n");
+    }
+
+    #[test]
+    fn uart_handle_keeps_error_behind_fmt_error() {
+        use core::fmt::Write;
+        let mut device = device();
+        device.transport.i2c.fail_remaining = 1;
+
+        let mut uart = device.channel(Channel::A);
+        assert_eq!(uart.write_str("x"), Err(core::fmt::Error));
+        assert_eq!(uart.last_error(), Some(Error::Bus(MockError)));
+    }
+
+    #[test]
+    fn uart_handle_read_drains_up_to_buffer() {
+        let mut device = fifo_device();
+        device.transport.i2c.rx[0].extend(b"abcdef");
+        assert_eq!(device.peek(Channel::A).unwrap(), Some(b'a'));
+
+        let mut uart = device.channel(Channel::A);
+        let mut buf = [0; 4];
+        assert_eq!(uart.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"abcd");
+        assert_eq!(uart.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], b"ef");
+        assert!(!uart.read_ready().unwrap());
+        assert_eq!(uart.read(&mut []).unwrap(), 0);
+    }
+
+    #[test]
+    fn uart_handle_read_waits_for_a_byte() {
+        let mut device = fifo_device();
+        // Nothing received for the first two level checks
+        device.transport.i2c.script(Channel::A, 0x09, &[0, 0]);
+        device.transport.i2c.rx[0].extend(b"z");
+
+        let mut buf = [0; 4];
+        assert_eq!(
+            embedded_io::Read::read(&mut device.channel(Channel::A), &mut buf).unwrap(),
+            1
+        );
+        assert_eq!(buf[0], b'z');
+        assert_eq!(device.transport.i2c.reads_of(Channel::A, 0x09), 3);
+    }
+
+    #[test]
+    fn uart_handle_write_waits_for_space_when_full() {
+        let mut device = device();
+        device.transport.i2c.script(Channel::B, 0x08, &[0, 64]);
+        // THR not yet empty on the first poll
+        device.transport.i2c.script(Channel::B, 0x05, &[0x00, 0x20]);
+
+        let mut uart = device.channel(Channel::B);
+        assert!(!uart.write_ready().unwrap());
+        assert_eq!(embedded_io::Write::write(&mut uart, b"abc").unwrap(), 3);
+        assert_eq!(device.transport.i2c.tx[1], b"abc");
+    }
+
+    #[test]
+    fn uart_handle_write_times_out_when_thr_stays_full() {
+        let mut device = device();
+        device.transport.i2c.regs[0][0x08] = 0;
+        device.transport.i2c.regs[0][0x05] = 0x00;
+
+        assert_eq!(
+            device.channel(Channel::A).write(b"abc"),
+            Err(Error::Timeout)
+        );
+        assert_eq!(
+            embedded_io::Error::kind(&Error::<MockError>::Timeout),
+            embedded_io::ErrorKind::TimedOut
+        );
+    }
+
+    #[test]
+    fn uart_handle_flush_waits_for_transmitter_empty() {
+        let mut device = device();
+        device.transport.i2c.script(Channel::A, 0x05, &[0x20, 0x20]);
+
+        device.channel(Channel::A).flush().unwrap();
+        assert_eq!(device.transport.i2c.reads_of(Channel::A, 0x05), 3);
+    }
+
+    #[test]
+    fn uart_handle_flush_times_out_without_temt() {
+        let mut device = device();
+        device.transport.i2c.regs[1][0x05] = 0x20;
+
+        assert_eq!(device.channel(Channel::B).flush(), Err(Error::Timeout));
+        assert_eq!(
+            device.transport.i2c.reads_of(Channel::B, 0x05),
+            THR_EMPTY_POLLS as usize
+        );
+    }
+
+    #[test]
+    fn write_nonblocking_stops_at_txlvl() {
+        let mut device = device();
+        device.transport.i2c.regs[0][0x08] = 4;
+
+        assert_eq!(
+            device.write_nonblocking(Channel::A, b"abcdefgh").unwrap(),
+            4
+        );
+        assert_eq!(device.transport.i2c.tx[0], b"abcd");
+        assert_eq!(device.transport.i2c.reads_of(Channel::A, 0x08), 1);
+        assert_eq!(device.transport.i2c.reads_of(Channel::A, 0x05), 0);
+
+        device.transport.i2c.regs[0][0x08] = 0;
+        assert_eq!(device.write_nonblocking(Channel::A, b"ijkl").unwrap(), 0);
+        assert_eq!(device.transport.i2c.tx[0], b"abcd");
+    }
+
+    #[test]
+    fn reads_follow_per_channel_fifo_state() {
+        let mut device = fifo_device();
+        device.fifo_enable(Channel::B, false).unwrap();
+        device.transport.i2c.rx[0].extend(b"abc");
+        device.transport.i2c.rx[1].extend(b"xyz");
+        assert!(device.fifo_enabled(Channel::A));
+        assert!(!device.fifo_enabled(Channel::B));
+
+        assert_eq!(device.read_all(Channel::A).unwrap(), b"abc");
+        assert!(device.transport.i2c.reads_of(Channel::A, 0x09) > 0);
+        assert_eq!(device.transport.i2c.reads_of(Channel::A, 0x05), 0);
+
+        // Without FIFOs only the holding register is visible, one byte per LSR check
+        assert_eq!(device.read_all(Channel::B).unwrap(), b"x");
+        assert_eq!(device.transport.i2c.reads_of(Channel::B, 0x09), 0);
+        assert_eq!(device.transport.i2c.reads_of(Channel::B, 0x05), 1);
+    }
+
+    #[test]
+    fn reset_device_clears_fifo_shadow() {
+        let mut device = device();
+        device.reset_device().unwrap();
+        assert!(!device.fifo_enabled(Channel::A));
+        assert!(!device.fifo_enabled(Channel::B));
+    }
+
+    #[test]
+    fn wait_tx_space_polls_until_enough_is_free() {
+        let mut device = device();
+        let mut delay = MockDelay::default();
+        device.transport.i2c.script(Channel::A, 0x08, &[2, 2, 8]);
+
+        device
+            .wait_tx_space(Channel::A, 8, Duration::from_millis(1), &mut delay)
+            .unwrap();
+        assert_eq!(device.transport.i2c.reads_of(Channel::A, 0x08), 3);
+        assert_eq!(delay.total_us, 200);
+    }
+
+    #[test]
+    fn wait_tx_space_times_out() {
+        let mut device = device();
+        let mut delay = MockDelay::default();
+        device.transport.i2c.regs[0][0x08] = 2;
+
+        assert_eq!(
+            device.wait_tx_space(Channel::A, 8, Duration::from_millis(1), &mut delay),
+            Err(Error::Timeout)
+        );
+        assert_eq!(delay.total_us, 1000);
+    }
+
+    #[test]
+    fn flush_all_waits_for_temt_on_both_channels() {
+        let mut device = device();
+        let mut delay = MockDelay::default();
+        device.transport.i2c.script(Channel::A, 0x05, &[0x20, 0x60]);
+        device
+            .transport
+            .i2c
+            .script(Channel::B, 0x05, &[0x00, 0x20, 0x20, 0x60]);
+
+        device
+            .flush_all(Duration::from_millis(1), &mut delay)
+            .unwrap();
+        assert_eq!(device.transport.i2c.reads_of(Channel::A, 0x05), 2);
+        assert_eq!(device.transport.i2c.reads_of(Channel::B, 0x05), 4);
+        assert_eq!(delay.total_us, 300);
+
+        device.transport.i2c.regs[1][0x05] = 0x20;
+        assert_eq!(
+            device.flush_all(Duration::from_millis(1), &mut delay),
+            Err(Error::Timeout)
+        );
+    }
+
+    #[test]
+    fn poll_into_returns_the_filled_part_of_scratch() {
+        let mut device = fifo_device();
+        let mut scratch = [0; FIFO_DEPTH];
+        device.transport.i2c.rx[0].extend(100..120);
+
+        let received = device.poll_into(Channel::A, &mut scratch).unwrap();
+        assert_eq!(received.len(), 20);
+        assert_eq!(received, (100..120).collect::<Vec<u8>>());
+        assert!(device
+            .poll_into(Channel::A, &mut scratch)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn read_bulk_is_one_level_check_and_one_block_read() {
+        let mut device = fifo_device();
+        device.transport.i2c.rx[1].extend(0..40);
+
+        let mut buf = [0; 32];
+        assert_eq!(device.read_bulk(Channel::B, &mut buf).unwrap(), 32);
+        assert_eq!(buf[..], (0..32).collect::<Vec<u8>>()[..]);
+        assert_eq!(
+            device.transport.i2c.log,
+            [
+                Op::Read {
+                    channel: 1,
+                    reg: 0x09,
+                    len: 1
+                },
+                Op::Read {
+                    channel: 1,
+                    reg: 0x00,
+                    len: 32
+                },
+            ]
+        );
+
+        assert_eq!(device.read_bulk(Channel::B, &mut buf).unwrap(), 8);
+        assert_eq!(buf[..8], [32, 33, 34, 35, 36, 37, 38, 39]);
+    }
+
+    #[test]
+    fn configure_rx_timeout_interrupt_raises_trigger_and_enables_rhr() {
+        let mut device = device();
+        device.transport.i2c.regs[0][0x01] = 0x02;
+        device.transport.i2c.tcr_tlr[0][1] = 0x23;
+
+        device.configure_rx_timeout_interrupt(Channel::A).unwrap();
+        assert_eq!(device.transport.i2c.tcr_tlr[0][1], 0xF3);
+        assert_eq!(device.transport.i2c.regs[0][0x01], 0x03);
+    }
+
+    #[test]
+    fn write_bulk_is_one_combined_write() {
+        let mut device = device();
+
+        assert_eq!(device.write_bulk(Channel::A, b"ping").unwrap(), 4);
+        assert_eq!(
+            device.transport.i2c.log,
+            [
+                Op::Read {
+                    channel: 0,
+                    reg: 0x08,
+                    len: 1
+                },
+                Op::Write {
+                    channel: 0,
+                    reg: 0x00,
+                    data: b"ping".to_vec()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn close_disables_interrupts_drains_and_resets() {
+        let mut device = device();
+        device.transport.i2c.regs[1][0x01] = 0x07;
+        device.transport.i2c.script(Channel::B, 0x05, &[0x20]);
+
+        device.close(Channel::B, false).unwrap();
+        assert_eq!(writes(&device), [(1, 0x01, 0x00), (1, 0x02, 0x06)]);
+        assert_eq!(device.transport.i2c.reads_of(Channel::B, 0x05), 2);
+        assert!(!device.get_features(Channel::B).unwrap().tx_disable);
+    }
+
+    #[test]
+    fn close_times_out_when_transmitter_never_empties() {
+        let mut device = device();
+        device
+            .initialise(Channel::A, UartConfig::default())
+            .unwrap();
+        device.transport.i2c.regs[0][0x05] = 0x20;
+
+        assert_eq!(device.close(Channel::A, false), Err(Error::Timeout));
+        assert_eq!(device.transport.i2c.regs[0][0x01], 0x00);
+        assert_eq!(device.configured_channels().count(), 1);
+    }
+
+    #[test]
+    fn close_can_disable_transmitter_and_receiver() {
+        let mut device = device();
+        device.close(Channel::A, true).unwrap();
+
+        let features = device.get_features(Channel::A).unwrap();
+        assert!(features.tx_disable);
+        assert!(features.rx_disable);
+    }
+
+    #[test]
+    fn set_baudrate_rejects_unreachable_rates() {
+        let mut device = device();
+
+        assert_eq!(
+            device.set_baudrate_with_fifo_reset(Channel::A, 1_000_000),
+            Err(Error::Config(ConfigError::BaudrateTooHigh))
+        );
+        assert_eq!(
+            device.set_baudrate_with_fifo_reset(Channel::A, 0),
+            Err(Error::Config(ConfigError::BaudrateTooLow))
+        );
+        assert_eq!(
+            device.set_baudrate_with_fifo_reset(Channel::A, 1),
+            Err(Error::Config(ConfigError::BaudrateTooLow))
+        );
+        assert!(writes(&device).is_empty());
+    }
+
+    #[test]
+    fn set_baudrate_programs_16_bit_divisor() {
+        let mut device = device();
+        device.set_baudrate_with_fifo_reset(Channel::B, 50).unwrap();
+        assert_eq!(device.transport.i2c.divisor[1], [0x00, 0x09]);
+    }
+
+    #[test]
+    fn baudrate_error_ppm_reports_rounding() {
+        let device = device();
+        assert_eq!(device.baudrate_error_ppm(Channel::A, 9600), Ok(0));
+        // Divisor 11 gives 10472 baud
+        assert_eq!(device.baudrate_error_ppm(Channel::A, 10_000), Ok(47_200));
+        assert_eq!(
+            device.baudrate_error_ppm(Channel::A, 230_400),
+            Err(ConfigError::BaudrateTooHigh)
+        );
+    }
+
+    #[test]
+    fn get_flow_thresholds_decodes_tcr_nibbles() {
+        let mut device = device();
+        device.transport.i2c.tcr_tlr[0][0] = 0x48;
+        assert_eq!(device.get_flow_thresholds(Channel::A).unwrap(), (32, 16));
+
+        device.set_flow_thresholds_bytes(Channel::B, 60, 8).unwrap();
+        assert_eq!(device.get_flow_thresholds(Channel::B).unwrap(), (60, 8));
+    }
+
+    #[test]
+    fn baudrate_divisor_follows_crystal() {
+        let mut device = device();
+        device
+            .set_baudrate_with_fifo_reset(Channel::A, 9600)
+            .unwrap();
+        assert_eq!(device.transport.i2c.divisor[0], [12, 0]);
+
+        let mut device = SC16IS752::new_with_crystal(0x48, MockI2c::new(), 14_745_600).unwrap();
+        device
+            .set_baudrate_with_fifo_reset(Channel::A, 9600)
+            .unwrap();
+        assert_eq!(device.transport.i2c.divisor[0], [96, 0]);
+        assert!(device
+            .supported_baud_rates(Channel::A)
+            .any(|(baud, _)| baud == 115200));
+    }
+
+    #[test]
+    fn calibrate_crystal_changes_divisor() {
+        let mut device = device();
+        device
+            .set_baudrate_with_fifo_reset(Channel::A, 300)
+            .unwrap();
+        assert_eq!(device.transport.i2c.divisor[0], [0x80, 0x01]);
+
+        device.calibrate_crystal(1_850_000);
+        assert_eq!(device.crystal_hz(), 1_850_000);
+        device
+            .set_baudrate_with_fifo_reset(Channel::A, 300)
+            .unwrap();
+        assert_eq!(device.transport.i2c.divisor[0], [0x81, 0x01]);
+    }
+
+    #[test]
+    fn line_status_decodes_lsr() {
+        let mut device = device();
+        device
+            .transport
+            .i2c
+            .script(Channel::B, 0x05, &[0x60, 0x8B, 0x13]);
+
+        let idle = device.line_status(Channel::B).unwrap();
+        assert!(idle.thr_empty && idle.transmitter_empty);
+        assert!(!idle.data_ready && !idle.overrun_error && !idle.fifo_data_error);
+
+        let framing = device.line_status(Channel::B).unwrap();
+        assert_eq!(
+            framing,
+            LineStatus {
+                data_ready: true,
+                overrun_error: true,
+                parity_error: false,
+                framing_error: true,
+                break_interrupt: false,
+                fifo_data_error: true,
+                thr_empty: false,
+                transmitter_empty: false,
+            }
+        );
+
+        let line_break = device.line_status(Channel::B).unwrap();
+        assert!(line_break.break_interrupt && line_break.overrun_error && line_break.data_ready);
+        assert!(!line_break.parity_error && !line_break.framing_error);
+    }
+
+    #[test]
+    fn gpio_availability_follows_modem_mux() {
+        let mut device = device();
+        assert_eq!(device.gpio_availability().unwrap().available, 0xFF);
+
+        device.modem_pin(true).unwrap();
+        let availability = device.gpio_availability().unwrap();
+        assert_eq!(availability.available, 0x0F);
+        assert!(availability.is_available(GPIO::GPIO3));
+        assert!(!availability.is_available(GPIO::GPIO4));
+        assert!(!availability.is_available(GPIO::GPIO7));
+
+        device.transport.i2c.regs[0][0x0E] = 0x06;
+        assert_eq!(device.gpio_availability().unwrap().available, 0x00);
+        device.set_variant(Variant::SC16IS750);
+        assert_eq!(device.gpio_availability().unwrap().available, 0x0F);
+    }
+
+    #[test]
+    fn set_hardware_flow_control_unlocks_efr() {
+        let mut device = device();
+        device.transport.i2c.regs[0][0x03] = 0x03;
+        device.transport.i2c.enhanced[0][0x02] = 0x4A;
+
+        device
+            .set_hardware_flow_control(Channel::A, false, true)
+            .unwrap();
+        assert_eq!(
+            device.transport.i2c.log,
+            [
+                Op::Read {
+                    channel: 0,
+                    reg: 0x03,
+                    len: 1
+                },
+                Op::Write {
+                    channel: 0,
+                    reg: 0x03,
+                    data: vec![0xBF]
+                },
+                Op::Read {
+                    channel: 0,
+                    reg: 0x02,
+                    len: 1
+                },
+                Op::Write {
+                    channel: 0,
+                    reg: 0x02,
+                    data: vec![0x9A]
+                },
+                Op::Write {
+                    channel: 0,
+                    reg: 0x03,
+                    data: vec![0x03]
+                },
+            ]
+        );
+
+        device
+            .set_hardware_flow_control(Channel::A, true, true)
+            .unwrap();
+        assert_eq!(device.transport.i2c.enhanced[0][0x02], 0xDA);
+    }
+
+    #[test]
+    fn write_with_gap_delays_between_bytes() {
+        let mut device = device();
+        let mut delay = MockDelay::default();
+
+        device
+            .write_with_gap(Channel::A, b"abc", Duration::from_micros(500), &mut delay)
+            .unwrap();
+        assert_eq!(device.transport.i2c.tx[0], b"abc");
+        assert_eq!(delay.total_us, 1000);
+    }
+
+    #[test]
+    fn write_with_gap_times_out_when_byte_never_finishes() {
+        let mut device = device();
+        let mut delay = MockDelay::default();
+        device.transport.i2c.regs[1][0x05] = 0x20;
+
+        assert_eq!(
+            device.write_with_gap(Channel::B, b"abc", Duration::from_micros(500), &mut delay),
+            Err(Error::Timeout)
+        );
+        assert_eq!(device.transport.i2c.tx[1], b"a");
+        assert_eq!(delay.total_us, 0);
+    }
+
+    #[test]
+    fn software_flow_control_round_trips() {
+        let mut device = device();
+        device.transport.i2c.regs[1][0x03] = 0x03;
+        device.transport.i2c.enhanced[1][0x02] = 0xD0;
+
+        device.set_xon_xoff_chars(Channel::B, 0x11, 0x13).unwrap();
+        device
+            .set_software_flow_control(Channel::B, SwFlowMode::XonXoff1)
+            .unwrap();
+        assert_eq!(device.transport.i2c.enhanced[1][0x02], 0xDA);
+        assert_eq!(
+            device.transport.i2c.enhanced[1][0x04..],
+            [0x11, 0x11, 0x13, 0x13]
+        );
+        assert_eq!(device.transport.i2c.regs[1][0x03], 0x03);
+        assert_eq!(
+            device.get_software_flow_control(Channel::B).unwrap(),
+            (SwFlowMode::XonXoff1, 0x11, 0x13)
+        );
+
+        device
+            .set_software_flow_control(Channel::B, SwFlowMode::None)
+            .unwrap();
+        assert_eq!(device.transport.i2c.enhanced[1][0x02], 0xD0);
+    }
+
+    #[test]
+    fn read_byte_checked_attributes_errors_to_the_byte() {
+        let mut device = device();
+        device.transport.i2c.rx[0].extend([0x41, 0x42, 0x00]);
+        device
+            .transport
+            .i2c
+            .script(Channel::A, 0x05, &[0x65, 0x61, 0xF9, 0x60]);
+
+        assert_eq!(
+            device.read_byte_checked(Channel::A).unwrap(),
+            Err(LineError::Parity(0x41))
+        );
+        assert_eq!(device.read_byte_checked(Channel::A).unwrap(), Ok(0x42));
+        assert_eq!(
+            device.read_byte_checked(Channel::A).unwrap(),
+            Err(LineError::Break)
+        );
+        assert_eq!(
+            device.read_byte_checked(Channel::A).unwrap(),
+            Err(LineError::Empty)
+        );
+        assert_eq!(device.transport.i2c.reads_of(Channel::A, 0x00), 3);
+    }
+
+    #[test]
+    fn set_loopback_echoes_writes_back() {
+        let mut device = fifo_device();
+        device.transport.i2c.regs[0][0x04] = 0x02;
+
+        device.set_loopback(Channel::A, true).unwrap();
+        assert_eq!(device.transport.i2c.regs[0][0x04], 0x12);
+        device.write(Channel::A, b"self test").unwrap();
+        assert_eq!(device.read_all(Channel::A).unwrap(), b"self test");
+
+        device.set_loopback(Channel::A, false).unwrap();
+        device.write(Channel::A, b"xyz").unwrap();
+        assert!(device.read_all(Channel::A).unwrap().is_empty());
+        assert_eq!(device.transport.i2c.regs[0][0x04], 0x02);
+    }
+
+    #[test]
+    fn set_dtr_and_modem_status() {
+        let mut device = device();
+        device.set_dtr(Channel::B, true).unwrap();
+        assert_eq!(device.transport.i2c.regs[1][0x04], 0x01);
+        device.set_dtr(Channel::B, false).unwrap();
+        assert_eq!(device.transport.i2c.regs[1][0x04], 0x00);
+
+        device.transport.i2c.regs[1][0x06] = 0x91;
+        assert_eq!(
+            device.modem_status(Channel::B).unwrap(),
+            ModemStatus {
+                cts: true,
+                dsr: false,
+                ri: false,
+                cd: true,
+            }
+        );
+    }
+
+    #[test]
+    fn set_sleep_with_wake_enables_efr_then_sleep_and_rx() {
+        let mut device = device();
+        device.transport.i2c.regs[0][0x01] = 0x02;
+
+        device.set_sleep_with_wake(Channel::A).unwrap();
+        assert_eq!(device.transport.i2c.enhanced[0][0x02], 0x00);
+        assert_eq!(device.transport.i2c.regs[0][0x01], 0x13);
+
+        let writes = writes(&device);
+        let efr = writes.iter().position(|w| *w == (0, 0x02, 0x10)).unwrap();
+        let ier = writes.iter().position(|w| *w == (0, 0x01, 0x13)).unwrap();
+        assert!(efr < ier);
+    }
+
+    #[test]
+    fn set_sleep_unlocks_efr_and_toggles_ier_bit_4() {
+        let mut device = device();
+        device.transport.i2c.regs[1][0x01] = 0x03;
+
+        device.set_sleep(Channel::B, true).unwrap();
+        assert_eq!(device.transport.i2c.enhanced[1][0x02], 0x00);
+        assert_eq!(device.transport.i2c.regs[1][0x01], 0x13);
+        let writes = writes(&device);
+        let efr = writes.iter().position(|w| *w == (1, 0x02, 0x10)).unwrap();
+        let ier = writes.iter().position(|w| *w == (1, 0x01, 0x13)).unwrap();
+        assert!(efr < ier);
+
+        device.set_sleep(Channel::B, false).unwrap();
+        assert_eq!(device.transport.i2c.regs[1][0x01], 0x03);
+    }
+
+    #[test]
+    fn set_sleep_refused_while_tx_pending() {
+        let mut device = device();
+        device.transport.i2c.regs[0][0x05] = 0x20;
+        device.transport.i2c.regs[0][0x08] = 60;
+
+        assert_eq!(device.set_sleep(Channel::A, true), Err(Error::TxNotEmpty));
+        assert_eq!(device.transport.i2c.regs[0][0x01], 0x00);
+        assert!(writes(&device).is_empty());
+        device.set_sleep(Channel::A, false).unwrap();
+    }
+
+    #[test]
+    fn set_prescaler_changes_the_230400_divisor() {
+        let mut device = device();
+        device.calibrate_crystal(14_745_600);
+
+        device
+            .set_prescaler(Channel::A, Prescaler::DivideBy4)
+            .unwrap();
+        assert_eq!(device.transport.i2c.enhanced[0][0x02], 0x00);
+        assert!(writes(&device).contains(&(0, 0x02, 0x10)));
+        assert_eq!(device.transport.i2c.regs[0][0x04], 0x80);
+        device.set_baudrate(Channel::A, 230_400).unwrap();
+        let divide_by_4 = device.transport.i2c.divisor[0];
+
+        device
+            .set_prescaler(Channel::A, Prescaler::DivideBy1)
+            .unwrap();
+        assert_eq!(device.transport.i2c.regs[0][0x04], 0x00);
+        device.set_baudrate(Channel::A, 230_400).unwrap();
+        let divide_by_1 = device.transport.i2c.divisor[0];
+
+        assert_eq!(divide_by_4, [0x01, 0x00]);
+        assert_eq!(divide_by_1, [0x04, 0x00]);
+    }
+
+    #[test]
+    fn baudrate_error_follows_set_prescaler() {
+        let mut device = device();
+        device.calibrate_crystal(14_745_600);
+        // 921600 baud base clock: divisor 9 gives 102400 baud
+        assert_eq!(device.baudrate_error_ppm(Channel::A, 100_000), Ok(24_000));
+        assert!(device
+            .supported_baud_rates(Channel::A)
+            .any(|(baud, _)| baud == 115200));
+
+        device
+            .set_prescaler(Channel::A, Prescaler::DivideBy4)
+            .unwrap();
+        // 230400 baud base clock: divisor 2 gives 115200 baud
+        assert_eq!(device.baudrate_error_ppm(Channel::A, 100_000), Ok(152_000));
+        assert_eq!(device.baudrate_error_ppm(Channel::B, 100_000), Ok(24_000));
+        assert_eq!(
+            device.baudrate_error_ppm(Channel::A, 250_000),
+            Err(ConfigError::BaudrateTooHigh)
+        );
+        assert!(device
+            .supported_baud_rates(Channel::A)
+            .all(|(_, error)| error.abs() <= 1));
+    }
+
+    #[test]
+    fn set_baudrate_ignores_non_prescaler_mcr_bits() {
+        let mut device = device();
+        device.set_rts(Channel::B, true).unwrap();
+        device.set_baudrate(Channel::B, 115_200).unwrap();
+        assert_eq!(device.transport.i2c.divisor[1], [0x01, 0x00]);
+    }
+
+    #[test]
+    fn set_break_toggles_only_lcr_bit_6() {
+        let mut device = device();
+        device.transport.i2c.regs[1][0x03] = 0x1B;
+
+        device.set_break(Channel::B, true).unwrap();
+        assert_eq!(device.transport.i2c.regs[1][0x03], 0x5B);
+        device.set_break(Channel::B, false).unwrap();
+        assert_eq!(device.transport.i2c.regs[1][0x03], 0x1B);
+
+        // Break set while the line format changes, and DLAB kept for a caller that holds it
+        device.set_break(Channel::B, true).unwrap();
+        device.set_line(Channel::B, 7, Parity::Odd, 1).unwrap();
+        assert_eq!(device.transport.i2c.regs[1][0x03], 0x4A);
+        device.transport.i2c.regs[1][0x03] = 0x83;
+        device.set_break(Channel::B, true).unwrap();
+        assert_eq!(device.transport.i2c.regs[1][0x03], 0xC3);
+    }
+
+    #[test]
+    fn compute_divisor_and_actual_baud_known_combinations() {
+        assert_eq!(compute_divisor(1_843_200, 1, 9600), 12);
+        assert_eq!(actual_baud(1_843_200, 1, 12), 9600);
+        assert_eq!(compute_divisor(1_843_200, 1, 115_200), 1);
+        assert_eq!(compute_divisor(1_843_200, 4, 2400), 12);
+        assert_eq!(compute_divisor(14_745_600, 1, 921_600), 1);
+        assert_eq!(compute_divisor(14_745_600, 4, 9600), 24);
+        assert_eq!(actual_baud(14_745_600, 4, 24), 9600);
+        // Rounded down, so the actual rate lands above the request
+        assert_eq!(compute_divisor(12_000_000, 1, 115_200), 6);
+        assert_eq!(actual_baud(12_000_000, 1, 6), 125_000);
+
+        assert_eq!(compute_divisor(1_843_200, 1, 230_400), 0);
+        assert_eq!(compute_divisor(1_843_200, 1, 0), 0);
+        assert_eq!(actual_baud(1_843_200, 1, 0), 0);
+    }
+
+    #[test]
+    fn dump_registers_reads_bank_except_rhr() {
+        let mut device = device();
+        device.transport.i2c.rx[1].extend([0xEE]);
+        for reg in 1..16 {
+            device.transport.i2c.regs[1][reg] = reg as u8 * 0x10;
+        }
+        device.transport.i2c.regs[1][0x05] = 0x60;
+
+        let registers = device.dump_registers(Channel::B).unwrap();
+        let reads: Vec<u8> = device
+            .transport
+            .i2c
+            .log
+            .iter()
+            .map(|op| match op {
+                Op::Read {
+                    channel: 1, reg, ..
+                } => *reg,
+                _ => panic!("unexpected {op:?}"),
+            })
+            .collect();
+        assert_eq!(
+            reads,
+            [0x03, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]
+        );
+        assert_eq!(registers[0x00], 0x00);
+        assert_eq!(registers[0x01], 0x10);
+        assert_eq!(registers[0x05], 0x61);
+        assert_eq!(registers[0x09], 1);
+        assert_eq!(device.transport.i2c.rx[1].len(), 1);
+    }
+
+    #[test]
+    fn dump_registers_clears_dlab_and_restores_lcr() {
+        let mut device = device();
+        device.transport.i2c.regs[0][0x03] = 0x83;
+        device.transport.i2c.regs[0][0x01] = 0x05;
+        device.transport.i2c.divisor[0] = [0x0C, 0x00];
+
+        let registers = device.dump_registers(Channel::A).unwrap();
+        assert_eq!(registers[0x01], 0x05);
+        assert_eq!(registers[0x03], 0x83);
+        assert_eq!(device.transport.i2c.regs[0][0x03], 0x83);
+        assert_eq!(writes(&device), [(0, 0x03, 0x03), (0, 0x03, 0x83)]);
+
+        let dump = format!("{:?}", RegisterDump::from(registers));
+        assert!(dump.starts_with("RegisterDump { IER: 0x05, IIR: 0x00, LCR: 0x83"));
+        assert!(dump.ends_with("RHR: not read }"));
+    }
+
+    #[test]
+    fn uart_config_validate_rejects_bad_frames() {
+        assert_eq!(UartConfig::default().validate(), Ok(()));
+        assert_eq!(
+            UartConfig::default().word_length(9).validate(),
+            Err(ConfigError::InvalidWordLength)
+        );
+        assert_eq!(
+            UartConfig::default().word_length(4).validate(),
+            Err(ConfigError::InvalidWordLength)
+        );
+        assert_eq!(
+            UartConfig::default().stop_bit(0).validate(),
+            Err(ConfigError::InvalidStopBits)
+        );
+        assert_eq!(
+            UartConfig::default()
+                .word_length(5)
+                .parity(Parity::Even)
+                .stop_bit(3)
+                .validate(),
+            Err(ConfigError::InvalidStopBits)
+        );
+    }
+
+    #[test]
+    fn initialise_rejects_invalid_config_before_writing() {
+        let mut device = device();
+        assert_eq!(
+            device.initialise(Channel::A, UartConfig::default().word_length(9)),
+            Err(Error::Config(ConfigError::InvalidWordLength))
+        );
+        assert!(device.transport.i2c.log.is_empty());
+    }
+
+    #[test]
+    fn initialise_enables_and_resets_fifos_then_sets_line_and_clears_interrupts() {
+        let mut device = device();
+        device
+            .initialise(Channel::A, UartConfig::default().baudrate(9600))
+            .unwrap();
+
+        assert_eq!(
+            writes(&device),
+            [
+                (0, 0x02, 0x01),
+                (0, 0x02, 0x07),
+                (0, 0x03, 0x80),
+                (0, 0x00, 0x0C),
+                (0, 0x01, 0x00),
+                (0, 0x03, 0x00),
+                (0, 0x03, 0x03),
+            ]
+        );
+        let log = &device.transport.i2c.log;
+        assert!(matches!(
+            log[log.len() - 3..],
+            [
+                Op::Read { reg: 0x02, .. },
+                Op::Read { reg: 0x05, .. },
+                Op::Read { reg: 0x06, .. }
+            ]
+        ));
+        assert!(device.fifo_enabled(Channel::A));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn initalise_uart_is_initialise() {
+        let mut device = device();
+        device
+            .initalise_uart(Channel::B, UartConfig::default())
+            .unwrap();
+        assert!(matches!(
+            device.configured_channels().collect::<Vec<_>>()[..],
+            [Channel::B]
+        ));
+    }
+
+    #[test]
+    fn configured_channels_lists_initialised_channels() {
+        let mut device = device();
+        assert_eq!(device.configured_channels().count(), 0);
+
+        device
+            .initialise(Channel::A, UartConfig::default().baudrate(9600))
+            .unwrap();
+        let channels: Vec<Channel> = device.configured_channels().collect();
+        assert!(matches!(channels[..], [Channel::A]));
+
+        device.close(Channel::A, false).unwrap();
+        assert_eq!(device.configured_channels().count(), 0);
+    }
+
+    #[test]
+    fn next_interrupt_service_loop_ends_when_nothing_pending() {
+        let mut device = device();
+        device
+            .transport
+            .i2c
+            .script(Channel::A, 0x02, &[0xC6, 0xCC, 0xC2, 0xC1]);
+
+        let mut events = vec![];
+        while let Some(event) = device.next_interrupt(Channel::A).unwrap() {
+            events.push(event);
+        }
+        assert_eq!(
+            events,
+            [
+                InterruptEvent::RECEIVE_LINE_STATUS_ERROR,
+                InterruptEvent::RECEIVE_TIMEOUT_INTERRUPT,
+                InterruptEvent::THR_INTERRUPT,
+            ]
+        );
+        assert_eq!(device.transport.i2c.reads_of(Channel::A, 0x02), 4);
+        assert_eq!(
+            device.last_interrupt(Channel::A),
+            Some(InterruptEvent::THR_INTERRUPT)
+        );
+
+        device.transport.i2c.script(Channel::A, 0x02, &[0xC1]);
+        assert_eq!(device.isr(Channel::A).unwrap(), InterruptEvent::UNKNOWN);
+    }
+
+    #[cfg(feature = "trace")]
+    #[test]
+    fn trace_hook_sees_every_access() {
+        static EVENTS: std::sync::Mutex<Vec<TraceEvent>> = std::sync::Mutex::new(Vec::new());
+        fn record(event: TraceEvent) {
+            EVENTS.lock().unwrap().push(event);
+        }
+
+        let mut device = fifo_device();
+        device.transport.i2c.rx[1].extend([0x55, 0x66]);
+        device.set_trace(Some(record));
+        device.set_rts(Channel::A, true).unwrap();
+        let mut buf = [0; 2];
+        device.read_bulk(Channel::B, &mut buf).unwrap();
+        device.set_trace(None);
+        device.set_rts(Channel::A, false).unwrap();
+
+        use TraceEvent::{Read, Write};
+        assert_eq!(
+            *EVENTS.lock().unwrap(),
+            [
+                Read {
+                    channel: Channel::A,
+                    reg: 0x04,
+                    value: 0x00
+                },
+                Write {
+                    channel: Channel::A,
+                    reg: 0x04,
+                    value: 0x02
+                },
+                Read {
+                    channel: Channel::B,
+                    reg: 0x09,
+                    value: 2
+                },
+                Read {
+                    channel: Channel::B,
+                    reg: 0x00,
+                    value: 0x55
+                },
+                Read {
+                    channel: Channel::B,
+                    reg: 0x00,
+                    value: 0x66
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn set_gpio_channel_split_toggles_iocontrol_bit_1() {
+        let mut device = device();
+        device.transport.i2c.regs[0][0x0E] = 0x01;
+
+        device.set_gpio_channel_split(true).unwrap();
+        assert_eq!(device.transport.i2c.regs[0][0x0E], 0x03);
+        device.set_gpio_channel_split(false).unwrap();
+        assert_eq!(device.transport.i2c.regs[0][0x0E], 0x01);
+        assert!(writes(&device).iter().all(|(channel, _, _)| *channel == 0));
+    }
+
+    #[test]
+    fn estimated_drain_time_from_txlvl_and_config() {
+        let mut device = device();
+        assert_eq!(device.estimated_drain_time(Channel::A).unwrap(), None);
+
+        device
+            .initialise(Channel::A, UartConfig::default().baudrate(9600))
+            .unwrap();
+        device.transport.i2c.regs[0][0x08] = 48;
+        let estimate = device.estimated_drain_time(Channel::A).unwrap().unwrap();
+        assert_eq!(estimate.as_micros(), 16_666);
+    }
+
+    #[test]
+    fn uart_config_char_time_counts_parity_and_stop_bits() {
+        let config = UartConfig::new(9600, 7, Parity::Even, 2);
+        assert_eq!(config.bits_per_char(), 11);
+        assert_eq!(config.char_time(96), Duration::from_millis(110));
+    }
+
+    #[test]
+    fn fifo_reset_writes_enable_with_reset_bits() {
+        let mut device = fifo_device();
+        device.fifo_reset(Channel::A, true, false).unwrap();
+        device.fifo_reset(Channel::A, false, true).unwrap();
+        device.fifo_reset(Channel::A, true, true).unwrap();
+        device.fifo_reset(Channel::A, false, false).unwrap();
+        assert_eq!(
+            writes(&device),
+            [(0, 0x02, 0x03), (0, 0x02, 0x05), (0, 0x02, 0x07)]
+        );
+
+        device.transport.i2c.log.clear();
+        device.fcr[1] = 0xC1;
+        device.fifo_reset(Channel::B, true, true).unwrap();
+        assert_eq!(writes(&device), [(1, 0x02, 0xC7)]);
+    }
+
+    #[test]
+    fn is_loopback_enabled_reads_mcr_bit_4() {
+        let mut device = device();
+        device.transport.i2c.regs[1][0x04] = 0x03;
+        assert!(!device.is_loopback_enabled(Channel::B).unwrap());
+
+        device.transport.i2c.regs[1][0x04] = 0x13;
+        assert!(device.is_loopback_enabled(Channel::B).unwrap());
+        assert!(!device.is_loopback_enabled(Channel::A).unwrap());
+    }
+
+    #[test]
+    fn flush_timeout_gives_up_on_stuck_thr() {
+        let mut device = device();
+        device.transport.i2c.regs[0][0x05] = 0x00;
+
+        assert_eq!(device.flush_timeout(Channel::A, 10), Err(Error::Timeout));
+        assert_eq!(device.transport.i2c.reads_of(Channel::A, 0x05), 10);
+        assert_eq!(device.write(Channel::A, b"x"), Err(Error::Timeout));
+        assert!(device.transport.i2c.tx[0].is_empty());
+
+        device.transport.i2c.script(Channel::B, 0x05, &[0x00, 0x00]);
+        device.flush_timeout(Channel::B, 3).unwrap();
+    }
+
+    #[test]
+    fn benchmark_channel_reports_loopback_throughput() {
+        let mut device = fifo_device();
+        let mut delay = MockDelay::default();
+        let mut ticks = [Duration::ZERO, Duration::from_millis(50)].into_iter();
+
+        let report = device
+            .benchmark_channel(Channel::A, 200, &mut delay, || ticks.next().unwrap())
+            .unwrap();
+        assert_eq!(
+            report,
+            ThroughputReport {
+                bytes_sent: 200,
+                bytes_received: 200,
+                errors: 0,
+                elapsed: Duration::from_millis(50),
+                bytes_per_sec: 4000,
+            }
+        );
+        assert!(!device.is_loopback_enabled(Channel::A).unwrap());
+    }
+
+    #[test]
+    fn benchmark_channel_counts_corrupted_bytes() {
+        let mut device = fifo_device();
+        let mut delay = MockDelay::default();
+        device.transport.i2c.loopback_xor = 0x01;
+
+        let report = device
+            .benchmark_channel(Channel::B, 10, &mut delay, || Duration::ZERO)
+            .unwrap();
+        assert_eq!(report.errors, 10);
+        assert_eq!(report.bytes_per_sec, 0);
+    }
+
+    #[test]
+    fn reset_device_waits_for_scratchpad() {
+        let mut device = device();
+        device
+            .transport
+            .i2c
+            .script(Channel::A, 0x07, &[0xFF, 0x00, 0x13]);
+
+        device.reset_device().unwrap();
+        assert_eq!(device.transport.i2c.regs[0][0x0E], 0x08);
+        // Three garbage reads, then a full ping of both channels
+        assert_eq!(device.transport.i2c.reads_of(Channel::A, 0x07), 5);
+        assert_eq!(device.transport.i2c.reads_of(Channel::B, 0x07), 2);
+    }
+
+    #[test]
+    fn reset_device_times_out_if_chip_never_returns() {
+        let mut device = device();
+        device.transport.i2c.script(Channel::A, 0x07, &[0xFF; 100]);
+
+        assert_eq!(device.reset_device(), Err(Error::Timeout));
+        assert_eq!(device.transport.i2c.reads_of(Channel::B, 0x07), 0);
+    }
+
+    #[test]
+    fn set_modem_outputs_composes_one_mcr_write() {
+        let mut device = device();
+        device.transport.i2c.regs[1][0x04] = 0x11;
+
+        device.set_modem_outputs(Channel::B, true, false).unwrap();
+        assert_eq!(writes(&device), [(1, 0x04, 0x12)]);
+        device.set_modem_outputs(Channel::B, true, true).unwrap();
+        assert_eq!(device.transport.i2c.regs[1][0x04], 0x13);
+        device.set_modem_outputs(Channel::B, false, false).unwrap();
+        assert_eq!(device.transport.i2c.regs[1][0x04], 0x10);
+    }
+
+    #[test]
+    fn release_returns_the_bus_for_reuse() {
+        let mut device = device();
+        device.set_rts(Channel::A, true).unwrap();
+
+        let mut i2c = device.release();
+        assert_eq!(i2c.log.len(), 2);
+        i2c.write(0x20, &[0x00, 0x01]).unwrap();
+        let device = SC16IS752::new(0x48, i2c).unwrap();
+        assert_eq!(device.release().log.len(), 3);
+    }
+
+    #[test]
+    fn verify_fifo_config_compares_iir_and_tlr_with_shadow() {
+        let mut device = fifo_device();
+        device.transport.i2c.regs[0][0x02] = 0xC1;
+        device.set_tx_trigger(Channel::A, 4).unwrap();
+        device.verify_fifo_config(Channel::A).unwrap();
+
+        // TLR lost the write
+        device.transport.i2c.tcr_tlr[0][1] = 0x00;
+        assert_eq!(device.verify_fifo_config(Channel::A), Err(Error::Mismatch));
+
+        // FIFOs enabled in the shadow but IIR says they are off
+        device.transport.i2c.regs[1][0x02] = 0x01;
+        assert_eq!(device.verify_fifo_config(Channel::B), Err(Error::Mismatch));
+    }
+
+    #[test]
+    fn set_trigger_levels_composes_tlr_and_restores_efr_mcr() {
+        let mut device = device();
+        device.transport.i2c.regs[0][0x03] = 0x03;
+        device.transport.i2c.regs[0][0x04] = 0x01;
+
+        device.set_trigger_levels(Channel::A, 12, 2).unwrap();
+        assert_eq!(device.transport.i2c.tcr_tlr[0][1], 0xC2);
+        assert_eq!(device.transport.i2c.enhanced[0][0x02], 0x00);
+        assert_eq!(device.transport.i2c.regs[0][0x04], 0x01);
+        assert_eq!(device.transport.i2c.regs[0][0x03], 0x03);
+        assert_eq!(
+            device.set_trigger_levels(Channel::A, 0, 2),
+            Err(Error::Config(ConfigError::InvalidTriggerLevel))
+        );
+        assert_eq!(
+            device.set_trigger_levels(Channel::A, 1, 16),
+            Err(Error::Config(ConfigError::InvalidTriggerLevel))
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn fifo_set_trigger_level_writes_its_tlr_nibble() {
+        let mut device = device();
+        device.fifo_set_trigger_level(Channel::B, true, 8).unwrap();
+        device.fifo_set_trigger_level(Channel::B, false, 3).unwrap();
+        assert_eq!(device.transport.i2c.tcr_tlr[1][1], 0x83);
+        assert_eq!(device.transport.i2c.regs[1][0x07], 0x00);
+    }
+
+    #[test]
+    fn clear_special_char_reads_iir_and_clears() {
+        let mut device = device();
+        // Xoff/special character pending, then the read clears it back to "no interrupt"
+        device.transport.i2c.script(Channel::B, 0x02, &[0x10, 0x01]);
+
+        assert!(device.clear_special_char(Channel::B).unwrap());
+        assert_eq!(device.transport.i2c.reads_of(Channel::B, 0x02), 1);
+        assert!(!device.clear_special_char(Channel::B).unwrap());
+        assert_eq!(device.transport.i2c.reads_of(Channel::B, 0x02), 2);
     }
 }
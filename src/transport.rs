@@ -0,0 +1,272 @@
+//! Register access over the two host interfaces of the SC16IS752
+//!
+//! Both I2C and SPI address a register with the same sub-address byte: register address in
+//! bits 6:3 and channel in bits 2:1. Over SPI bit 7 is the R/W flag, set for reads.
+
+use crate::{Channel, FIFO_DEPTH};
+use embedded_hal::i2c::blocking::I2c;
+use embedded_hal::spi::blocking::{SpiBus, SpiBusRead, SpiBusWrite, SpiDevice};
+
+/// Reads and writes SC16IS752 registers over a host bus
+pub trait Transport {
+    /// Error type of the underlying bus
+    type Error: core::fmt::Debug;
+
+    /// Reads a single register
+    fn read_register(&mut self, channel: Channel, reg: u8) -> Result<u8, Self::Error>;
+
+    /// Writes a single register
+    fn write_register(&mut self, channel: Channel, reg: u8, value: u8) -> Result<(), Self::Error>;
+
+    /// Reads `buf.len()` bytes from the same register in one transaction, used to drain RHR
+    fn read_registers(
+        &mut self,
+        channel: Channel,
+        reg: u8,
+        buf: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        for byte in buf.iter_mut() {
+            *byte = self.read_register(channel, reg)?;
+        }
+        Ok(())
+    }
+
+    /// Writes `data` to the same register in one transaction, used to fill THR
+    fn write_registers(
+        &mut self,
+        channel: Channel,
+        reg: u8,
+        data: &[u8],
+    ) -> Result<(), Self::Error> {
+        for byte in data {
+            self.write_register(channel, reg, *byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Register sub-address byte shared by I2C and SPI
+fn sub_address(channel: Channel, reg: u8) -> u8 {
+    reg << 3 | (channel as u8) << 1
+}
+
+/// SC16IS752 on an I2C bus
+#[derive(Debug)]
+pub struct I2cTransport<I2C> {
+    pub(crate) address: u8,
+    pub(crate) i2c: I2C,
+}
+
+impl<I2C> I2cTransport<I2C> {
+    /// Accepts either the 7-bit address (0x48 to 0x57) or the 8-bit form from the datasheet
+    pub fn new(device_address: u8, i2c: I2C) -> Self {
+        let mut address = device_address;
+        if !(0x48..=0x57).contains(&device_address) {
+            address = device_address >> 1
+        }
+        Self { address, i2c }
+    }
+}
+
+impl<I2C: I2c> Transport for I2cTransport<I2C> {
+    type Error = I2C::Error;
+
+    fn read_register(&mut self, channel: Channel, reg: u8) -> Result<u8, Self::Error> {
+        let mut result = [0];
+        self.i2c
+            .write_read(self.address, &[sub_address(channel, reg)], &mut result)?;
+        Ok(result[0])
+    }
+
+    fn write_register(&mut self, channel: Channel, reg: u8, value: u8) -> Result<(), Self::Error> {
+        self.i2c
+            .write(self.address, &[sub_address(channel, reg), value])
+    }
+
+    fn read_registers(
+        &mut self,
+        channel: Channel,
+        reg: u8,
+        buf: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.i2c
+            .write_read(self.address, &[sub_address(channel, reg)], buf)
+    }
+
+    fn write_registers(
+        &mut self,
+        channel: Channel,
+        reg: u8,
+        data: &[u8],
+    ) -> Result<(), Self::Error> {
+        let mut buf = [0u8; FIFO_DEPTH + 1];
+        buf[0] = sub_address(channel, reg);
+        for chunk in data.chunks(FIFO_DEPTH) {
+            buf[1..=chunk.len()].copy_from_slice(chunk);
+            self.i2c.write(self.address, &buf[..=chunk.len()])?;
+        }
+        Ok(())
+    }
+}
+
+/// SC16IS752 on an SPI bus, chip select handled by the [`SpiDevice`]
+#[derive(Debug)]
+pub struct SpiTransport<SPI> {
+    pub(crate) spi: SPI,
+}
+
+impl<SPI> SpiTransport<SPI> {
+    #[allow(missing_docs)]
+    pub fn new(spi: SPI) -> Self {
+        Self { spi }
+    }
+}
+
+impl<SPI> Transport for SpiTransport<SPI>
+where
+    SPI: SpiDevice,
+    SPI::Bus: SpiBus,
+{
+    type Error = SPI::Error;
+
+    fn read_register(&mut self, channel: Channel, reg: u8) -> Result<u8, Self::Error> {
+        let mut result = [0];
+        self.read_registers(channel, reg, &mut result)?;
+        Ok(result[0])
+    }
+
+    fn write_register(&mut self, channel: Channel, reg: u8, value: u8) -> Result<(), Self::Error> {
+        self.write_registers(channel, reg, &[value])
+    }
+
+    fn read_registers(
+        &mut self,
+        channel: Channel,
+        reg: u8,
+        buf: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.spi.transaction(|bus| {
+            bus.write(&[0x80 | sub_address(channel, reg)])?;
+            bus.read(buf)
+        })
+    }
+
+    fn write_registers(
+        &mut self,
+        channel: Channel,
+        reg: u8,
+        data: &[u8],
+    ) -> Result<(), Self::Error> {
+        self.spi.transaction(|bus| {
+            bus.write(&[sub_address(channel, reg)])?;
+            bus.write(data)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal::spi::blocking::SpiBusFlush;
+    use embedded_hal::spi::{ErrorKind, ErrorType};
+    use std::collections::VecDeque;
+
+    #[derive(Debug)]
+    struct MockError;
+
+    impl embedded_hal::spi::Error for MockError {
+        fn kind(&self) -> ErrorKind {
+            ErrorKind::Other
+        }
+    }
+
+    /// Records the MOSI bytes of every chip select frame and answers reads from a queue
+    #[derive(Debug, Default)]
+    struct MockSpi {
+        frames: Vec<Vec<u8>>,
+        miso: VecDeque<u8>,
+    }
+
+    impl ErrorType for MockSpi {
+        type Error = MockError;
+    }
+
+    impl SpiBusFlush for MockSpi {
+        fn flush(&mut self) -> Result<(), MockError> {
+            Ok(())
+        }
+    }
+
+    impl SpiBusRead for MockSpi {
+        fn read(&mut self, words: &mut [u8]) -> Result<(), MockError> {
+            for word in words {
+                *word = self.miso.pop_front().unwrap_or(0);
+            }
+            Ok(())
+        }
+    }
+
+    impl SpiBusWrite for MockSpi {
+        fn write(&mut self, words: &[u8]) -> Result<(), MockError> {
+            self.frames.last_mut().unwrap().extend_from_slice(words);
+            Ok(())
+        }
+    }
+
+    impl SpiBus for MockSpi {
+        fn transfer(&mut self, _read: &mut [u8], _write: &[u8]) -> Result<(), MockError> {
+            unimplemented!()
+        }
+
+        fn transfer_in_place(&mut self, _words: &mut [u8]) -> Result<(), MockError> {
+            unimplemented!()
+        }
+    }
+
+    impl SpiDevice for MockSpi {
+        type Bus = Self;
+
+        fn transaction<R>(
+            &mut self,
+            f: impl FnOnce(&mut Self) -> Result<R, MockError>,
+        ) -> Result<R, MockError> {
+            self.frames.push(vec![]);
+            f(self)
+        }
+    }
+
+    #[test]
+    fn spi_write_frames_register_and_value() {
+        let mut transport = SpiTransport::new(MockSpi::default());
+        transport.write_register(Channel::B, 0x03, 0xBF).unwrap();
+        transport
+            .write_registers(Channel::A, 0x00, &[1, 2, 3])
+            .unwrap();
+
+        assert_eq!(
+            transport.spi.frames,
+            [vec![0x1A, 0xBF], vec![0x00, 1, 2, 3]]
+        );
+    }
+
+    #[test]
+    fn spi_read_sets_read_bit() {
+        let mut transport = SpiTransport::new(MockSpi::default());
+        transport.spi.miso.extend([0x60, 7, 8]);
+
+        assert_eq!(transport.read_register(Channel::A, 0x05).unwrap(), 0x60);
+        let mut buf = [0; 2];
+        transport
+            .read_registers(Channel::B, 0x00, &mut buf)
+            .unwrap();
+
+        assert_eq!(buf, [7, 8]);
+        assert_eq!(transport.spi.frames, [vec![0xA8], vec![0x82]]);
+    }
+
+    #[test]
+    fn i2c_address_accepts_8_bit_form() {
+        assert_eq!(I2cTransport::new(0x48, ()).address, 0x48);
+        assert_eq!(I2cTransport::new(0x90, ()).address, 0x48);
+    }
+}